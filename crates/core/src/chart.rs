@@ -0,0 +1,152 @@
+use crate::{prisma::mask_counter, Mask};
+use chrono::Duration;
+use plotters::{coord::Shift, prelude::*, style::register_font};
+use std::{fmt, path::Path, sync::Once};
+
+const CAPTION_FONT: (&str, u32) = ("sans-serif", 40);
+const LABEL_FONT: (&str, u32) = ("sans-serif", 20);
+/// Bundled so charts look the same everywhere, regardless of the fonts installed.
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+static FONT_REGISTERED: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+	/// The total count over time.
+	TimeSeries,
+	/// The total count of every mask.
+	Breakdown,
+}
+
+impl ChartKind {
+	pub const ALL: [ChartKind; 2] = [ChartKind::TimeSeries, ChartKind::Breakdown];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			ChartKind::TimeSeries => "Counts over time",
+			ChartKind::Breakdown => "Counts per mask",
+		}
+	}
+}
+
+impl fmt::Display for ChartKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+/// Draws a chart of `data` onto any plotters backend. `data` is expected to be sorted by date.
+/// Without any data, the chart is drawn empty with a label saying so.
+pub fn draw<DB: DrawingBackend>(
+	root: &DrawingArea<DB, Shift>,
+	kind: ChartKind,
+	masks: &[Mask],
+	data: &[mask_counter::Data],
+) -> crate::Result<()>
+where
+	DB::ErrorType: 'static,
+{
+	FONT_REGISTERED.call_once(|| {
+		register_font("sans-serif", FontStyle::Normal, FONT).expect("the bundled font is valid");
+	});
+	root.fill(&WHITE)?;
+
+	let (Some(first), Some(last)) = (data.first(), data.last()) else {
+		let root = root.titled(kind.name(), CAPTION_FONT)?;
+		let (width, height) = root.dim_in_pixel();
+		root.draw(&Text::new(
+			"No counts yet",
+			(width as i32 / 2, height as i32 / 2),
+			LABEL_FONT
+				.into_font()
+				.color(&BLACK)
+				.pos(Pos::new(HPos::Center, VPos::Center)),
+		))?;
+		return Ok(());
+	};
+
+	match kind {
+		ChartKind::TimeSeries => {
+			// NOTE: a single count or counts at the same time would leave an empty range
+			let end = last.date.max(first.date + Duration::minutes(1));
+			let mut chart = ChartBuilder::on(root)
+				.caption(kind.name(), CAPTION_FONT)
+				.margin(20)
+				.set_label_area_size(LabelAreaPosition::Left, 60)
+				.set_label_area_size(LabelAreaPosition::Bottom, 60)
+				.build_cartesian_2d(first.date..end, 0..data.len())?;
+
+			chart
+				.configure_mesh()
+				.disable_x_mesh()
+				.disable_y_mesh()
+				.x_label_formatter(&|date| date.format("%Y-%m-%d").to_string())
+				.label_style(LABEL_FONT)
+				.draw()?;
+
+			chart.draw_series(
+				AreaSeries::new(
+					data.iter()
+						.enumerate()
+						.map(|(index, counter)| (counter.date, index + 1)),
+					0,
+					RED.mix(0.2),
+				)
+				.border_style(RED),
+			)?;
+		}
+		ChartKind::Breakdown => {
+			let counts = masks
+				.iter()
+				.map(|mask| {
+					data.iter()
+						.filter(|counter| counter.r#type == mask.discriminant() as i32)
+						.count()
+				})
+				.collect::<Vec<_>>();
+			let max = counts.iter().copied().max().unwrap_or_default();
+
+			let mut chart = ChartBuilder::on(root)
+				.caption(kind.name(), CAPTION_FONT)
+				.margin(20)
+				.set_label_area_size(LabelAreaPosition::Left, 60)
+				.set_label_area_size(LabelAreaPosition::Bottom, 60)
+				.build_cartesian_2d((0..masks.len()).into_segmented(), 0..max + 1)?;
+
+			chart
+				.configure_mesh()
+				.disable_x_mesh()
+				.x_label_formatter(&|value| match value {
+					SegmentValue::CenterOf(index) => masks
+						.get(*index)
+						.map(|mask| String::from(mask.name()))
+						.unwrap_or_default(),
+					_ => String::new(),
+				})
+				.label_style(LABEL_FONT)
+				.draw()?;
+
+			chart.draw_series(
+				Histogram::vertical(&chart)
+					.style(RED.mix(0.5).filled())
+					.margin(20)
+					.data(counts.into_iter().enumerate()),
+			)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Renders a chart offscreen and saves it as a PNG of `size` pixels at `path`.
+pub fn export_png(
+	path: impl AsRef<Path>,
+	size: (u32, u32),
+	kind: ChartKind,
+	masks: &[Mask],
+	data: &[mask_counter::Data],
+) -> crate::Result<()> {
+	let root = BitMapBackend::new(path.as_ref(), size).into_drawing_area();
+	draw(&root, kind, masks, data)?;
+	root.present()?;
+	Ok(())
+}
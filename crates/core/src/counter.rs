@@ -0,0 +1,869 @@
+use crate::{
+	last_reset,
+	last_sent::LastSent,
+	prisma::{mask_counter, stat_record, PrismaClient, SortOrder},
+	records::{self, NewRecord, RecordKind, RecordTracker, SESSION_GAP},
+	since_reset, split_count, trigger_active, Config, Mask, MaskSide, QuietHours, SegmentedOutput,
+	ITERATION_SIZE,
+};
+use chrono::{DateTime, FixedOffset, Local, SubsecRound, TimeDelta, Utc};
+use futures::{channel::mpsc::Sender, SinkExt};
+use prisma_client_rust::{prisma_errors::query_engine::UniqueKeyViolation, QueryError};
+use rosc::{OscMessage, OscPacket, OscType};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use rust_decimal_macros::dec;
+use std::{
+	collections::{HashMap, HashSet},
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex, RwLock,
+	},
+	time::Duration,
+};
+use tokio::{
+	net::UdpSocket,
+	sync::Notify,
+	time::{self, Instant},
+};
+use tracing::{debug, error, info, warn};
+
+pub const MASK_COUNTER_PARAM: &str = "/avatar/parameters/mask_counter";
+pub const MASK_ITERATION_PARAM: &str = "/avatar/parameters/mask_iteration";
+/// The amount of distinct OSC addresses remembered for the settings' address picker.
+pub const OSC_ADDRESS_LIMIT: usize = 200;
+/// How often the counter lets the UI know it's still running, see `Event::Heartbeat`.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A blend tree is used inside the Unity Editor and uses a float parameter with a minimum range of
+/// negative one (-1) to a maximum range of positive one (+1). VRChat clamps remote parameters
+/// across the network to two decimal places (0.99). This gives a possible accurate range of 200
+/// values and this function is used to convert the integer form into the float that represents
+/// that integer by returning a `Decimal`.
+///
+/// Note that the function is not aware of a minimum/maximum range, therefore a `Decimal` can be
+/// returned with a value over positive one by giving a number greater than 200.
+///
+/// # Example
+///
+/// ```rust
+/// use rust_decimal::prelude::ToPrimitive;
+/// use vrcc_core::counter::int_to_decimal;
+///
+/// let num = 200;
+/// let dec = int_to_decimal(num).to_f32().unwrap();
+///
+/// assert_eq!(1.0, dec)
+/// ```
+pub fn int_to_decimal(num: usize) -> Decimal {
+	let output = Decimal::new(num as i64, 0) * dec!(0.01);
+	dec!(-1.0) + output
+}
+
+/// Encodes a single OSC message and sends it to VRChat's OSC input port at `target`.
+async fn send_osc(
+	socket: &UdpSocket,
+	target: SocketAddr,
+	addr: &str,
+	arg: OscType,
+) -> std::io::Result<usize> {
+	let buf = rosc::encoder::encode(&OscPacket::Message(OscMessage {
+		addr: String::from(addr),
+		args: vec![arg],
+	}))
+	.unwrap();
+
+	socket.send_to(&buf, target).await
+}
+
+/// Every float address the counter is sent through.
+fn output_addresses(segmented_output: Option<&SegmentedOutput>) -> Vec<String> {
+	match segmented_output {
+		Some(segmented_output) => (0..segmented_output.segments)
+			.map(|index| segmented_output.address(index))
+			.collect(),
+		None => vec![
+			String::from(MASK_COUNTER_PARAM),
+			String::from(MASK_ITERATION_PARAM),
+		],
+	}
+}
+
+/// Sends parameters to VRChat and lets the UI know when sending starts failing or works again.
+struct Output<'a> {
+	socket: &'a UdpSocket,
+	target: SocketAddr,
+	tx: Sender<Event>,
+	last_sent: LastSent,
+	failing: bool,
+}
+
+impl<'a> Output<'a> {
+	fn new(
+		socket: &'a UdpSocket,
+		target: SocketAddr,
+		tx: Sender<Event>,
+		last_sent: LastSent,
+	) -> Self {
+		Self {
+			socket,
+			target,
+			tx,
+			last_sent,
+			failing: false,
+		}
+	}
+
+	async fn send(&mut self, addr: &str, arg: OscType) -> bool {
+		match send_osc(self.socket, self.target, addr, arg).await {
+			Ok(_) => {
+				if self.failing {
+					self.failing = false;
+					self.tx.send(Event::SendRecovered).await.unwrap();
+				}
+				true
+			}
+			Err(e) => {
+				error!("Couldn't send to {}: {}", addr, e);
+				self.failing = true;
+				self.tx
+					.send(Event::SendFailed(format!(
+						"OSC send to {} failed, is VRChat's OSC enabled? ({})",
+						addr, e
+					)))
+					.await
+					.unwrap();
+				false
+			}
+		}
+	}
+
+	/// Sends a float parameter and caches it once it was sent, see `save_last_sent`.
+	async fn send_float(&mut self, addr: &str, value: f32) {
+		if self.send(addr, OscType::Float(value)).await {
+			self.last_sent.set(addr, value);
+		}
+	}
+
+	/// Writes the values sent since the last call to disk, dropping the ones of addresses that
+	/// aren't part of the current output anymore.
+	async fn save_last_sent(&mut self, segmented_output: Option<&SegmentedOutput>) {
+		self.last_sent.retain(&output_addresses(segmented_output));
+		if let Err(e) = self.last_sent.save().await {
+			error!("Couldn't cache the last sent values: {}", e);
+		}
+	}
+
+	/// Sends the whole counter, either as the counter and iteration parameters or as every segment
+	/// of the segmented output.
+	async fn send_counter(
+		&mut self,
+		segmented_output: Option<&SegmentedOutput>,
+		data_len: usize,
+		iteration_amount: usize,
+	) {
+		if let Some(segmented_output) = segmented_output {
+			let total = iteration_amount * ITERATION_SIZE + data_len;
+			let values = segmented_output.segment_values(total);
+			for (index, value) in values.into_iter().enumerate() {
+				let address = segmented_output.address(index);
+				let output = int_to_decimal(value);
+				info!("output: {}", output);
+				info!("affected address: {}", address);
+				self.send_float(&address, output.to_f32().unwrap()).await;
+			}
+			return;
+		}
+
+		let output = int_to_decimal(data_len);
+		info!("output: {}", output);
+		info!("affected address: {}", MASK_COUNTER_PARAM);
+		self.send_float(MASK_COUNTER_PARAM, output.to_f32().unwrap())
+			.await;
+
+		info!("iteration_amount: {}", iteration_amount);
+		let output = int_to_decimal(iteration_amount);
+		self.send_float(MASK_ITERATION_PARAM, output.to_f32().unwrap())
+			.await;
+	}
+}
+
+/// A count that is waiting to be written with the next batch, see `Config::batch_window`.
+pub type PendingCount = (i32, DateTime<FixedOffset>);
+
+/// The date to store a count at. Dates are the primary key of the counts and stored with
+/// millisecond precision, so a count within the same millisecond as the `previous` one is dated a
+/// millisecond after it instead.
+///
+/// # Example
+///
+/// ```rust
+/// use chrono::{TimeDelta, Utc};
+/// use vrcc_core::counter::count_date;
+///
+/// let now = Utc::now().fixed_offset();
+/// let first = count_date(now, None);
+/// let second = count_date(now, Some(first));
+/// assert_eq!(first + TimeDelta::milliseconds(1), second);
+///
+/// let later = now + TimeDelta::seconds(1);
+/// assert!(count_date(later, Some(second)) > second + TimeDelta::milliseconds(1));
+/// ```
+pub fn count_date(
+	now: DateTime<FixedOffset>,
+	previous: Option<DateTime<FixedOffset>>,
+) -> DateTime<FixedOffset> {
+	let now = now.trunc_subsecs(3);
+	match previous {
+		Some(previous) if now <= previous => previous + TimeDelta::milliseconds(1),
+		_ => now,
+	}
+}
+
+/// Writes every pending count within a single transaction. When the transaction fails, the counts
+/// are written one by one instead. Counts that fail for a transient reason stay pending for the next
+/// flush, counts whose date is already taken never succeed and are dropped. Returns whether any
+/// counts are still pending.
+pub async fn flush_counts(db: &PrismaClient, pending_counts: &Mutex<Vec<PendingCount>>) -> bool {
+	let counts = std::mem::take(&mut *pending_counts.lock().unwrap());
+	if counts.is_empty() {
+		return false;
+	}
+
+	let creates = counts
+		.iter()
+		.map(|(kind, date)| {
+			db.mask_counter()
+				.create(*kind, vec![mask_counter::date::set(*date)])
+		})
+		.collect::<Vec<_>>();
+	match db._batch(creates).await {
+		Ok(_) => {
+			debug!("Wrote {} batched counts", counts.len());
+			return false;
+		}
+		Err(e) => error!(
+			"Couldn't write {} batched counts at once, writing them one by one: {}",
+			counts.len(),
+			e
+		),
+	}
+
+	let mut failed = Vec::new();
+	for (kind, date) in counts {
+		if let Err(e) = db
+			.mask_counter()
+			.create(kind, vec![mask_counter::date::set(date)])
+			.exec()
+			.await
+		{
+			if e.is_prisma_error::<UniqueKeyViolation>() {
+				error!("A count at {} already exists, dropping it: {}", date, e);
+			} else {
+				error!(
+					"Couldn't write a batched count, keeping it for the next flush: {}",
+					e
+				);
+				failed.push((kind, date));
+			}
+		}
+	}
+	if failed.is_empty() {
+		return false;
+	}
+
+	// NOTE: counts that came in during the flush are kept after the failed ones
+	let mut pending_counts = pending_counts.lock().unwrap();
+	failed.append(&mut pending_counts);
+	*pending_counts = failed;
+	true
+}
+
+/// Marks a reset in the database and sends the zeroed counter. Pending counts are written first so
+/// they're dated before the reset. Returns whether the counter was reset.
+async fn reset_counter(
+	db: &PrismaClient,
+	pending_counts: &Mutex<Vec<PendingCount>>,
+	output: &mut Output<'_>,
+	segmented_output: Option<&SegmentedOutput>,
+) -> bool {
+	flush_counts(db, pending_counts).await;
+	if let Err(e) = db.counter_reset().create(Vec::new()).exec().await {
+		error!("Couldn't reset the counter: {}", e);
+		return false;
+	}
+
+	output.send_counter(segmented_output, 0, 0).await;
+	output.tx.send(Event::Reset).await.unwrap();
+	true
+}
+
+fn best_record(records: &[stat_record::Data], kind: RecordKind) -> Option<usize> {
+	records
+		.iter()
+		.find(|record| record.kind == kind as i32)
+		.map(|record| record.value as usize)
+}
+
+/// Returns the best streak and session. Records that were never stored, e.g. because the counts
+/// predate them, are estimated from the stored counts instead.
+pub async fn best_records(
+	db: &PrismaClient,
+	streak_window: Duration,
+) -> std::result::Result<(Option<usize>, Option<usize>), QueryError> {
+	let records = db.stat_record().find_many(Vec::new()).exec().await?;
+	let mut best_streak = best_record(&records, RecordKind::Streak);
+	let mut best_session = best_record(&records, RecordKind::Session);
+
+	if best_streak.is_none() || best_session.is_none() {
+		let dates = db
+			.mask_counter()
+			.find_many(Vec::new())
+			.order_by(mask_counter::OrderByWithRelationParam::Date(SortOrder::Asc))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|counter| counter.date)
+			.collect::<Vec<_>>();
+
+		best_streak = best_streak.or_else(|| records::best_streak(&dates, streak_window));
+		best_session = best_session.or_else(|| records::best_session(&dates, SESSION_GAP));
+	}
+
+	Ok((best_streak, best_session))
+}
+
+/// Sets `active` to whether `quiet_hours` currently apply and lets the UI know when that changed.
+async fn update_quiet_hours(
+	quiet_hours: Option<QuietHours>,
+	active: &mut bool,
+	tx: &mut Sender<Event>,
+) {
+	let quiet = quiet_hours.is_some_and(|quiet_hours| quiet_hours.contains(Local::now().time()));
+	if quiet != *active {
+		*active = quiet;
+		info!("Quiet hours {}", if quiet { "started" } else { "ended" });
+		tx.send(Event::QuietHoursChanged(quiet)).await.unwrap();
+	}
+}
+
+/// A mask that passed every check and is about to be counted.
+#[derive(Debug, Clone)]
+struct Count {
+	param: Mask,
+	/// The OSC address that triggered the count.
+	addr: String,
+	/// When the count was received, a held back count is counted later.
+	at: std::time::Instant,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+	/// Contains the discriminant of the mask that counted.
+	CounterUpdated(u8),
+	RecordSet(NewRecord),
+	/// An OSC address that wasn't seen before, see `Shared::osc_addresses`.
+	OscReceived(String),
+	/// Whether counting is currently paused by the configured quiet hours.
+	QuietHoursChanged(bool),
+	/// Sending to VRChat failed, contains a message for the user.
+	SendFailed(String),
+	/// Sending to VRChat works again after it failed.
+	SendRecovered,
+	/// Sent every `HEARTBEAT_INTERVAL` while the counter is running.
+	Heartbeat,
+	/// The counter was reset to zero, either from the UI or remotely through OSC.
+	Reset,
+	Log(String),
+}
+
+/// Everything the counter shares with the UI. Both sides may change any of it at any time.
+#[derive(Debug, Clone)]
+pub struct Shared {
+	pub config: Arc<RwLock<Config>>,
+	/// Distinct OSC addresses received by the counter, newest last.
+	pub osc_addresses: Arc<RwLock<Vec<String>>>,
+	/// Discriminants of the masks that currently don't count.
+	pub muted_masks: Arc<RwLock<HashSet<u8>>>,
+	/// Counts that haven't been written yet, see `Config::batch_window`.
+	pub pending_counts: Arc<Mutex<Vec<PendingCount>>>,
+	/// Notified by the UI to reset the counter.
+	pub reset: Arc<Notify>,
+	/// The counts since the app was launched. Outlives the counter so a restart doesn't start a
+	/// new session.
+	pub session_total: Arc<AtomicUsize>,
+}
+
+/// Receives OSC packets on `socket`, counts the configured masks and sends the counter to VRChat
+/// at `target`. Everything the UI should know about is sent through `tx`. Runs until the task is
+/// dropped.
+pub async fn run_counter(
+	socket: UdpSocket,
+	target: SocketAddr,
+	db: Arc<PrismaClient>,
+	shared: Shared,
+	last_sent: LastSent,
+	mut tx: Sender<Event>,
+) {
+	let Shared {
+		config,
+		osc_addresses,
+		muted_masks,
+		pending_counts,
+		reset,
+		session_total,
+	} = shared;
+	let Config {
+		counter_offset,
+		streak_window,
+		segmented_output,
+		reset_on_launch,
+		..
+	} = config.read().unwrap().clone();
+
+	// NOTE: values of an older output config would set parameters the avatar doesn't use anymore
+	let mut last_sent = last_sent;
+	last_sent.retain(&output_addresses(segmented_output.as_ref()));
+
+	// NOTE: hold the avatar at its previous values until the database has been read, unless
+	// those values are outdated by a reset on launch
+	let restored = if reset_on_launch {
+		Vec::new()
+	} else {
+		last_sent
+			.values()
+			.map(|(addr, value)| (String::from(addr), value))
+			.collect::<Vec<_>>()
+	};
+	let mut output = Output::new(&socket, target, tx.clone(), last_sent);
+	for (addr, value) in restored {
+		info!("Restoring {} to {}", addr, value);
+		output.send(&addr, OscType::Float(value)).await;
+	}
+
+	// NOTE: get the start of the current day
+	// let start_cur_date = Local::now()
+	// 	.fixed_offset()
+	// 	.with_hour(0)
+	// 	.unwrap()
+	// 	.with_minute(0)
+	// 	.unwrap()
+	// 	.with_second(0)
+	// 	.unwrap()
+	// 	.with_nanosecond(0)
+	// 	.unwrap();
+
+	// NOTE: counts batched by a previous run of the counter would be missing from the count
+	flush_counts(&db, &pending_counts).await;
+
+	let last_reset = last_reset(&db).await.unwrap();
+	let db_count = db
+		.mask_counter()
+		// NOTE: only select records within the current day and grabbed instead of posed
+		// mask_counter::date::gt(start_cur_date),
+		// mask_counter::WhereParam::Or(vec![
+		// 	mask_counter::r#type::equals(
+		// 		Mask::UpGrabbed(Regex::new("").unwrap()).discriminant() as i32,
+		// 	),
+		// 	mask_counter::r#type::equals(
+		// 		Mask::DownGrabbed(Regex::new("").unwrap()).discriminant() as i32,
+		// 	),
+		// ]),
+		.find_many(since_reset(last_reset))
+		.exec()
+		.await
+		.unwrap()
+		.len();
+	let counter_offset = if last_reset.is_some() {
+		0
+	} else {
+		counter_offset
+	};
+	// NOTE: the offset is applied before the rollover so iterations are computed from the
+	// offset and the stored counts together
+	let (mut iteration_amount, mut data_len) = split_count(counter_offset + db_count);
+	output
+		.send_counter(segmented_output.as_ref(), data_len, iteration_amount)
+		.await;
+
+	let (best_streak, best_session) = best_records(&db, streak_window).await.unwrap();
+	let mut tracker = RecordTracker::new(streak_window, best_streak, best_session)
+		.with_session_total(session_total.load(Ordering::Relaxed));
+
+	let mut quiet_hours_active = false;
+
+	// NOTE: the pulse parameter is reset to false once this deadline passes
+	let mut pulse_deadline: Option<Instant> = None;
+	// NOTE: pending counts are written to the database once this deadline passes
+	let mut flush_deadline: Option<Instant> = None;
+	// NOTE: the counter is reset once this deadline passes while the reset parameter is held
+	let mut reset_deadline: Option<Instant> = None;
+	// NOTE: the side and time of the latest count, keyed by whether the mask is grabbed
+	let mut last_sides: HashMap<bool, (MaskSide, std::time::Instant)> = HashMap::new();
+	// NOTE: the time of the latest count of every mask, for the configured cooldowns
+	let mut last_counts: HashMap<u8, std::time::Instant> = HashMap::new();
+
+	// NOTE: counts of the non-priority side waiting out the mutual exclusion window, keyed by
+	// whether the mask is grabbed
+	let mut held_counts: HashMap<bool, (Count, Instant)> = HashMap::new();
+	// NOTE: the date of the latest count, so counts within the same millisecond get unique dates
+	let mut last_date: Option<DateTime<FixedOffset>> = None;
+
+	let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+
+	let mut buf = [0u8; rosc::decoder::MTU];
+	loop {
+		// NOTE: the config can be changed from the settings at any time
+		let Config {
+			avatar_params,
+			pulse_param,
+			pulse_duration,
+			batch_window,
+			trigger_type,
+			segmented_output,
+			quiet_hours,
+			mutual_exclusion,
+			reset_param,
+			reset_hold,
+			..
+		} = config.read().unwrap().clone();
+
+		if data_len >= ITERATION_SIZE {
+			info!("Setting iteration_amount and data_len!");
+			info!("iteration_amount: {}", iteration_amount);
+			info!("data_len: {}", data_len);
+			let (iterations, remainder) = split_count(data_len);
+			iteration_amount += iterations;
+			data_len = remainder;
+			info!("iteration_amount: {}", iteration_amount);
+			info!("data_len: {}", data_len);
+			// NOTE: segments are derived from the total count and have no iteration parameter
+			if segmented_output.is_none() {
+				let value = int_to_decimal(iteration_amount);
+				output
+					.send_float(MASK_ITERATION_PARAM, value.to_f32().unwrap())
+					.await;
+			}
+		}
+
+		// NOTE: written once per iteration instead of on every send to keep file writes off the
+		// hot path
+		output.save_last_sent(segmented_output.as_ref()).await;
+
+		// NOTE: masks that passed every check and count once the packet was handled
+		let mut counts: Vec<Count> = Vec::new();
+		let held_deadline = held_counts.values().map(|(_, deadline)| *deadline).min();
+
+		let received = tokio::select! {
+			received = socket.recv_from(&mut buf) => Some(received),
+			_ = time::sleep_until(pulse_deadline.unwrap_or_else(Instant::now)),
+				if pulse_deadline.is_some() =>
+			{
+				pulse_deadline = None;
+				if let Some(pulse_param) = &pulse_param {
+					output.send(pulse_param, OscType::Bool(false)).await;
+				}
+				continue;
+			}
+			_ = time::sleep_until(flush_deadline.unwrap_or_else(Instant::now)),
+				if flush_deadline.is_some() =>
+			{
+				flush_deadline = None;
+				if flush_counts(&db, &pending_counts).await
+					&& let Some(batch_window) = batch_window
+				{
+					flush_deadline = Some(Instant::now() + batch_window);
+				}
+				continue;
+			}
+			_ = time::sleep_until(reset_deadline.unwrap_or_else(Instant::now)),
+				if reset_deadline.is_some() =>
+			{
+				reset_deadline = None;
+				warn!(
+					"The reset parameter was held for {:?}, resetting the counter remotely!",
+					reset_hold
+				);
+				let segmented_output = segmented_output.as_ref();
+				if reset_counter(&db, &pending_counts, &mut output, segmented_output).await {
+					(iteration_amount, data_len) = (0, 0);
+				}
+				continue;
+			}
+			_ = reset.notified() => {
+				warn!("Resetting the counter");
+				let segmented_output = segmented_output.as_ref();
+				if reset_counter(&db, &pending_counts, &mut output, segmented_output).await {
+					(iteration_amount, data_len) = (0, 0);
+				}
+				continue;
+			}
+			_ = time::sleep_until(held_deadline.unwrap_or_else(Instant::now)),
+				if held_deadline.is_some() =>
+			{
+				let now = Instant::now();
+				let (due, held): (HashMap<_, _>, HashMap<_, _>) = held_counts
+					.drain()
+					.partition(|(_, (_, deadline))| *deadline <= now);
+				held_counts = held;
+				counts.extend(due.into_values().map(|(count, _)| count));
+				None
+			}
+			_ = heartbeat.tick() => {
+				tx.send(Event::Heartbeat).await.unwrap();
+				// NOTE: quiet hours also start and end while no packets arrive
+				update_quiet_hours(quiet_hours, &mut quiet_hours_active, &mut tx).await;
+				continue;
+			}
+		};
+
+		match received {
+			Some(Ok((size, addr))) => {
+				debug!("Received packet with size {} from: {}", &size, &addr);
+				let packet = match rosc::decoder::decode_udp(&buf[..size]) {
+					Ok((_, packet)) => packet,
+					Err(e) => {
+						error!("Couldn't decode packet from {}: {}", &addr, e);
+						continue;
+					}
+				};
+				match packet {
+					OscPacket::Message(msg) => {
+						debug!("OSC address: {}", &msg.addr);
+						debug!("OSC arguments: {:?}", &msg.args);
+
+						let is_new = {
+							let mut osc_addresses = osc_addresses.write().unwrap();
+							let is_new = !osc_addresses.contains(&msg.addr);
+							if is_new {
+								if osc_addresses.len() >= OSC_ADDRESS_LIMIT {
+									osc_addresses.remove(0);
+								}
+								osc_addresses.push(msg.addr.clone());
+							}
+							is_new
+						};
+						if is_new {
+							tx.send(Event::OscReceived(msg.addr.clone())).await.unwrap();
+						}
+
+						if reset_param.as_ref() == Some(&msg.addr) {
+							let held = msg
+								.args
+								.first()
+								.is_some_and(|arg| trigger_active(arg, trigger_type));
+							if held && reset_deadline.is_none() {
+								info!("Keep holding the reset parameter to reset the counter");
+								reset_deadline = Some(Instant::now() + reset_hold);
+							} else if !held && reset_deadline.take().is_some() {
+								info!("The reset parameter was released, not resetting");
+							}
+							continue;
+						}
+
+						update_quiet_hours(quiet_hours, &mut quiet_hours_active, &mut tx).await;
+
+						if let Some(arg) = msg.args.first()
+							&& trigger_active(arg, trigger_type)
+						{
+							let addr = msg.addr.as_str();
+							for param in &avatar_params {
+								if param.regex().find(addr).is_none() {
+									continue;
+								}
+
+								if quiet_hours_active {
+									info!("{} during quiet hours, not counting", param.name());
+									continue;
+								}
+
+								let muted =
+									muted_masks.read().unwrap().contains(&param.discriminant());
+								if muted {
+									debug!("{} is muted, skipping the count", param.name());
+									continue;
+								}
+
+								let now = Instant::now().into_std();
+								let remaining =
+									last_counts.get(&param.discriminant()).and_then(|last| {
+										config.read().unwrap().cooldown_remaining(
+											param.discriminant(),
+											*last,
+											now,
+										)
+									});
+								if let Some(remaining) = remaining {
+									debug!(
+										"{} is cooling down for {} ms, ignoring it",
+										param.name(),
+										remaining.as_millis()
+									);
+									continue;
+								}
+
+								let count = Count {
+									param: param.clone(),
+									addr: msg.addr.clone(),
+									at: now,
+								};
+								if let Some(mutual_exclusion) = &mutual_exclusion {
+									if mutual_exclusion.suppresses(
+										param.side(),
+										now,
+										last_sides.get(&param.is_grabbed()).copied(),
+									) {
+										info!(
+											"{} conflicts with the other side, not counting",
+											param.name()
+										);
+										continue;
+									}
+
+									if mutual_exclusion.holds_back(param.side()) {
+										info!(
+											"Holding {} back for {:?} in case the other side counts",
+											param.name(),
+											mutual_exclusion.window
+										);
+										let deadline = Instant::now() + mutual_exclusion.window;
+										// NOTE: a count that's already held back isn't replaced,
+										// it's counted right away
+										if let Some((held, _)) = held_counts
+											.insert(param.is_grabbed(), (count, deadline))
+										{
+											counts.push(held);
+										}
+										continue;
+									}
+
+									if let Some((held, _)) = held_counts.remove(&param.is_grabbed())
+									{
+										info!(
+											"{} conflicts with {}, dropping the held back count",
+											param.name(),
+											held.param.name()
+										);
+									}
+								}
+
+								counts.push(count);
+							}
+						} else if msg.addr == "/avatar/change" {
+							// TODO: configure avatar ids
+
+							info!("from address: {}", &msg.addr);
+							output
+								.send_counter(segmented_output.as_ref(), data_len, iteration_amount)
+								.await;
+						}
+					}
+					OscPacket::Bundle(bundle) => {
+						debug!("OSC Bundle: {:?}", &bundle);
+					}
+				}
+			}
+			Some(Err(e)) => {
+				error!("Error receiving from socket: {}", e);
+			}
+			None => {}
+		}
+
+		for Count { param, addr, at } in counts {
+			info!("{}!", param.name());
+			let date = count_date(Utc::now().fixed_offset(), last_date);
+			if let Some(batch_window) = batch_window {
+				pending_counts
+					.lock()
+					.unwrap()
+					.push((param.discriminant() as i32, date));
+				flush_deadline.get_or_insert_with(|| Instant::now() + batch_window);
+			} else if let Err(e) = db
+				.mask_counter()
+				.create(
+					param.discriminant() as i32,
+					vec![mask_counter::date::set(date)],
+				)
+				.exec()
+				.await
+			{
+				error!("{}", e);
+				continue;
+			}
+			last_date = Some(date);
+			last_sides.insert(param.is_grabbed(), (param.side(), at));
+			last_counts.insert(param.discriminant(), at);
+
+			if param.is_grabbed()
+				&& let Some(segmented_output) = &segmented_output
+			{
+				data_len += 1;
+
+				let total = iteration_amount * ITERATION_SIZE + data_len;
+				if let Some((index, value)) = segmented_output.changed_segment(total) {
+					let address = segmented_output.address(index);
+					let value = int_to_decimal(value);
+					info!("output: {}", value);
+					info!("from address: {}", &addr);
+					info!("affected address: {}", address);
+
+					output.send_float(&address, value.to_f32().unwrap()).await;
+				} else {
+					warn!(
+						"All {} segments are full, the avatar can't show a total of {}. Add more segments to keep counting on the avatar",
+						segmented_output.segments, total
+					);
+				}
+			} else if param.is_grabbed() {
+				data_len += 1;
+
+				let value = int_to_decimal(data_len);
+				info!("output: {}", value);
+				info!("from address: {}", &addr);
+				info!("affected address: {}", MASK_COUNTER_PARAM);
+
+				output
+					.send_float(MASK_COUNTER_PARAM, value.to_f32().unwrap())
+					.await;
+			}
+
+			let records = tracker.count(at);
+			session_total.store(tracker.session_total(), Ordering::Relaxed);
+			for record in records {
+				let now = Utc::now().fixed_offset();
+				if let Err(e) = db
+					.stat_record()
+					.upsert(
+						stat_record::kind::equals(record.kind as i32),
+						stat_record::create(
+							record.kind as i32,
+							record.value as i32,
+							vec![stat_record::date::set(now)],
+						),
+						vec![
+							stat_record::value::set(record.value as i32),
+							stat_record::date::set(now),
+						],
+					)
+					.exec()
+					.await
+				{
+					error!("{}", e);
+				}
+				tx.send(Event::RecordSet(record)).await.unwrap();
+			}
+
+			if let Some(pulse_param) = &pulse_param {
+				// NOTE: rapid counts extend the running pulse instead of resetting it early
+				if pulse_deadline.is_none() {
+					output.send(pulse_param, OscType::Bool(true)).await;
+				}
+				pulse_deadline = Some(Instant::now() + pulse_duration);
+			}
+
+			tx.send(Event::CounterUpdated(param.discriminant()))
+				.await
+				.unwrap();
+		}
+	}
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+pub const LAST_SENT_PATH: &str = "vrc-counter-last-sent.json";
+
+/// The last float values sent to the avatar by address. They're kept on disk so the avatar can be
+/// given its previous values right away on the next launch instead of flashing its defaults while
+/// the database is being read.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LastSent {
+	#[serde(skip)]
+	path: PathBuf,
+	/// Whether the values changed since they were last saved.
+	#[serde(skip)]
+	dirty: bool,
+	values: BTreeMap<String, f32>,
+}
+
+impl LastSent {
+	/// Loads the cached values, starting out empty when there are none or they can't be read.
+	pub fn load(path: impl Into<PathBuf>) -> Self {
+		let path = path.into();
+		let values = std::fs::read_to_string(&path)
+			.ok()
+			.and_then(|json| serde_json::from_str::<LastSent>(&json).ok())
+			.map(|last_sent| last_sent.values)
+			.unwrap_or_default();
+
+		Self {
+			path,
+			dirty: false,
+			values,
+		}
+	}
+
+	pub fn values(&self) -> impl Iterator<Item = (&str, f32)> {
+		self.values
+			.iter()
+			.map(|(address, value)| (address.as_str(), *value))
+	}
+
+	/// Caches a sent value in memory, see `save`.
+	pub fn set(&mut self, address: &str, value: f32) {
+		if self.values.insert(String::from(address), value) != Some(value) {
+			self.dirty = true;
+		}
+	}
+
+	/// Drops the values of addresses that aren't sent anymore, e.g. after the segmented output
+	/// changed.
+	pub fn retain(&mut self, addresses: &[String]) {
+		let len = self.values.len();
+		self.values.retain(|address, _| addresses.contains(address));
+		self.dirty |= self.values.len() != len;
+	}
+
+	/// Writes the values to disk when they changed since the last save. The file is replaced
+	/// through a rename so a crash while writing never leaves a partial file behind.
+	pub async fn save(&mut self) -> crate::Result<()> {
+		if !self.dirty {
+			return Ok(());
+		}
+
+		let json = serde_json::to_string(self)?;
+		let tmp_path = self.path.with_extension("json.tmp");
+		tokio::fs::write(&tmp_path, json).await?;
+		tokio::fs::rename(tmp_path, &self.path).await?;
+		self.dirty = false;
+		Ok(())
+	}
+}
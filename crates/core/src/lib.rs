@@ -1,22 +1,340 @@
+#![feature(let_chains)]
+
+pub mod chart;
+pub mod counter;
+pub mod last_sent;
+pub mod preset;
 #[allow(warnings, unused)]
 pub mod prisma;
+pub mod records;
 
-use prisma::PrismaClient;
+use chrono::{DateTime, FixedOffset, NaiveTime};
+use prisma::{counter_reset, mask_counter, PrismaClient, SortOrder};
+use prisma_client_rust::QueryError;
 use regex::Regex;
-use std::sync::Arc;
+use rosc::OscType;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
+use tracing::error;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub const AVATAR_PARAMETERS: &str = "/avatar/parameters/";
 
+/// The amount of counts a single float parameter can represent before rolling over into the next
+/// iteration. See `int_to_decimal` for why this is 200.
+pub const ITERATION_SIZE: usize = 200;
+
+/// Splits a total count into the amount of full iterations and the remaining count within the
+/// current iteration.
+///
+/// # Example
+///
+/// ```rust
+/// use vrcc_core::split_count;
+///
+/// // A counter offset of 350 with 60 counts stored in the database
+/// let (iteration_amount, data_len) = split_count(350 + 60);
+///
+/// assert_eq!(2, iteration_amount);
+/// assert_eq!(10, data_len);
+/// assert_eq!((0, 199), split_count(199));
+/// assert_eq!((1, 0), split_count(200));
+/// ```
+pub fn split_count(count: usize) -> (usize, usize) {
+	(count / ITERATION_SIZE, count % ITERATION_SIZE)
+}
+
+/// Output for avatars that spread the counter over several indexed float parameters, each covering
+/// `ITERATION_SIZE` counts, instead of using a counter and an iteration parameter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentedOutput {
+	/// The parameter address with `{}` as the placeholder for the segment index, e.g.
+	/// `/avatar/parameters/mask_counter_{}`.
+	pub template: String,
+	pub segments: usize,
+}
+
+impl SegmentedOutput {
+	pub fn address(&self, index: usize) -> String {
+		self.template.replace("{}", &index.to_string())
+	}
+
+	/// Returns the count held by every segment for a total count. Counts beyond the capacity of
+	/// all segments leave every segment full.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use vrcc_core::SegmentedOutput;
+	///
+	/// let output = SegmentedOutput {
+	/// 	template: String::from("/avatar/parameters/mask_counter_{}"),
+	/// 	segments: 3,
+	/// };
+	///
+	/// assert_eq!(vec![0, 0, 0], output.segment_values(0));
+	/// assert_eq!(vec![150, 0, 0], output.segment_values(150));
+	/// assert_eq!(vec![200, 0, 0], output.segment_values(200));
+	/// assert_eq!(vec![200, 1, 0], output.segment_values(201));
+	/// assert_eq!(vec![200, 200, 50], output.segment_values(450));
+	/// assert_eq!(vec![200, 200, 200], output.segment_values(1000));
+	/// assert_eq!("/avatar/parameters/mask_counter_2", output.address(2));
+	/// ```
+	pub fn segment_values(&self, total: usize) -> Vec<usize> {
+		(0..self.segments)
+			.map(|index| {
+				total
+					.saturating_sub(index * ITERATION_SIZE)
+					.min(ITERATION_SIZE)
+			})
+			.collect()
+	}
+
+	/// Returns the index and new count of the only segment that changes when the total count
+	/// increases to `total`, or `None` when there's no segment left to hold it.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use vrcc_core::SegmentedOutput;
+	///
+	/// let output = SegmentedOutput {
+	/// 	template: String::from("/avatar/parameters/mask_counter_{}"),
+	/// 	segments: 2,
+	/// };
+	///
+	/// assert_eq!(None, output.changed_segment(0));
+	/// assert_eq!(Some((0, 1)), output.changed_segment(1));
+	/// assert_eq!(Some((0, 200)), output.changed_segment(200));
+	/// assert_eq!(Some((1, 1)), output.changed_segment(201));
+	/// assert_eq!(Some((1, 200)), output.changed_segment(400));
+	/// assert_eq!(None, output.changed_segment(401));
+	/// ```
+	pub fn changed_segment(&self, total: usize) -> Option<(usize, usize)> {
+		let index = total.checked_sub(1)? / ITERATION_SIZE;
+		(index < self.segments).then_some((index, total - index * ITERATION_SIZE))
+	}
+}
+
+/// A daily window in local time during which counting is paused.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct QuietHours {
+	pub start: NaiveTime,
+	pub end: NaiveTime,
+}
+
+impl QuietHours {
+	/// Whether `time` is within the window, including `start` and excluding `end`. A window that
+	/// ends before it starts crosses midnight.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use chrono::NaiveTime;
+	/// use vrcc_core::QuietHours;
+	///
+	/// let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+	///
+	/// let overnight = QuietHours {
+	/// 	start: time(23),
+	/// 	end: time(7),
+	/// };
+	/// assert!(overnight.contains(time(23)));
+	/// assert!(overnight.contains(time(0)));
+	/// assert!(overnight.contains(time(6)));
+	/// assert!(!overnight.contains(time(7)));
+	/// assert!(!overnight.contains(time(12)));
+	///
+	/// let afternoon = QuietHours {
+	/// 	start: time(13),
+	/// 	end: time(15),
+	/// };
+	/// assert!(afternoon.contains(time(14)));
+	/// assert!(!afternoon.contains(time(15)));
+	/// assert!(!afternoon.contains(time(2)));
+	/// ```
+	pub fn contains(&self, time: NaiveTime) -> bool {
+		if self.start <= self.end {
+			self.start <= time && time < self.end
+		} else {
+			time >= self.start || time < self.end
+		}
+	}
+}
+
+/// The type a mask's trigger value is expected to arrive as. VRChat itself only sends bools for
+/// physbone parameters, the other types exist for OSC relays that don't preserve them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerType {
+	Bool,
+	Int,
+	Float,
+	/// Coerces between bools, ints and floats.
+	Any,
+}
+
+/// Whether a trigger value counts as active. Values of the expected type are read as is, ints and
+/// floats are active when they aren't zero. A value of another type is only accepted when the
+/// expected type is `TriggerType::Any`.
+///
+/// # Example
+///
+/// ```rust
+/// use rosc::OscType;
+/// use vrcc_core::{trigger_active, TriggerType};
+///
+/// assert!(trigger_active(&OscType::Bool(true), TriggerType::Bool));
+/// assert!(!trigger_active(&OscType::Bool(false), TriggerType::Bool));
+/// assert!(!trigger_active(&OscType::Int(1), TriggerType::Bool));
+/// assert!(!trigger_active(&OscType::Float(1.0), TriggerType::Bool));
+///
+/// assert!(trigger_active(&OscType::Int(1), TriggerType::Int));
+/// assert!(!trigger_active(&OscType::Int(0), TriggerType::Int));
+/// assert!(!trigger_active(&OscType::Bool(true), TriggerType::Int));
+/// assert!(!trigger_active(&OscType::Float(1.0), TriggerType::Int));
+///
+/// assert!(trigger_active(&OscType::Float(1.0), TriggerType::Float));
+/// assert!(!trigger_active(&OscType::Float(0.0), TriggerType::Float));
+/// assert!(!trigger_active(&OscType::Bool(true), TriggerType::Float));
+/// assert!(!trigger_active(&OscType::Int(1), TriggerType::Float));
+///
+/// assert!(trigger_active(&OscType::Bool(true), TriggerType::Any));
+/// assert!(trigger_active(&OscType::Int(1), TriggerType::Any));
+/// assert!(trigger_active(&OscType::Long(-1), TriggerType::Any));
+/// assert!(trigger_active(&OscType::Float(0.5), TriggerType::Any));
+/// assert!(trigger_active(&OscType::Double(1.0), TriggerType::Any));
+/// assert!(!trigger_active(&OscType::Bool(false), TriggerType::Any));
+/// assert!(!trigger_active(&OscType::Int(0), TriggerType::Any));
+/// assert!(!trigger_active(&OscType::Float(0.0), TriggerType::Any));
+/// assert!(!trigger_active(&OscType::String(String::from("true")), TriggerType::Any));
+/// ```
+pub fn trigger_active(arg: &OscType, expected: TriggerType) -> bool {
+	match (arg, expected) {
+		(OscType::Bool(value), TriggerType::Bool | TriggerType::Any) => *value,
+		(OscType::Int(value), TriggerType::Int | TriggerType::Any) => *value != 0,
+		(OscType::Long(value), TriggerType::Int | TriggerType::Any) => *value != 0,
+		(OscType::Float(value), TriggerType::Float | TriggerType::Any) => *value != 0.0,
+		(OscType::Double(value), TriggerType::Float | TriggerType::Any) => *value != 0.0,
+		_ => false,
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaskSide {
+	Up,
+	Down,
+}
+
+/// Makes the up and down masks of the same interaction mutually exclusive, for avatars whose
+/// overlapping physbones can report both at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutualExclusion {
+	/// How long after a count the other side of the same interaction doesn't count.
+	pub window: Duration,
+	/// The side that always counts. Counts of the other side are held back for `window` and
+	/// dropped if this side counts in the meantime. Without one, whichever side counts first wins.
+	pub priority: Option<MaskSide>,
+}
+
+impl MutualExclusion {
+	/// Whether a count of `side` at `now` conflicts with `last`, the side and time of the latest
+	/// count of the same interaction. The priority side never conflicts, it drops the held back
+	/// count of the other side instead, see `holds_back`.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use std::time::{Duration, Instant};
+	/// use vrcc_core::{MaskSide, MutualExclusion};
+	///
+	/// let start = Instant::now();
+	/// let later = start + Duration::from_millis(100);
+	/// let last = Some((MaskSide::Up, start));
+	///
+	/// let first_wins = MutualExclusion {
+	/// 	window: Duration::from_millis(500),
+	/// 	priority: None,
+	/// };
+	/// assert!(first_wins.suppresses(MaskSide::Down, later, last));
+	/// assert!(!first_wins.suppresses(MaskSide::Up, later, last));
+	/// assert!(!first_wins.suppresses(MaskSide::Down, later, None));
+	/// assert!(!first_wins.suppresses(MaskSide::Down, start + Duration::from_secs(1), last));
+	///
+	/// let down_wins = MutualExclusion {
+	/// 	priority: Some(MaskSide::Down),
+	/// 	..first_wins
+	/// };
+	/// assert!(down_wins.suppresses(MaskSide::Up, later, Some((MaskSide::Down, start))));
+	/// ```
+	pub fn suppresses(
+		&self,
+		side: MaskSide,
+		now: Instant,
+		last: Option<(MaskSide, Instant)>,
+	) -> bool {
+		if self.priority == Some(side) {
+			return false;
+		}
+
+		last.is_some_and(|(last_side, at)| {
+			last_side != side && now.saturating_duration_since(at) <= self.window
+		})
+	}
+
+	/// Whether counts of `side` have to wait out the window before they count, because the other
+	/// side has priority.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use std::time::Duration;
+	/// use vrcc_core::{MaskSide, MutualExclusion};
+	///
+	/// let down_wins = MutualExclusion {
+	/// 	window: Duration::from_millis(500),
+	/// 	priority: Some(MaskSide::Down),
+	/// };
+	/// assert!(down_wins.holds_back(MaskSide::Up));
+	/// assert!(!down_wins.holds_back(MaskSide::Down));
+	///
+	/// let first_wins = MutualExclusion {
+	/// 	priority: None,
+	/// 	..down_wins
+	/// };
+	/// assert!(!first_wins.holds_back(MaskSide::Up));
+	/// ```
+	pub fn holds_back(&self, side: MaskSide) -> bool {
+		self.priority.is_some_and(|priority| priority != side)
+	}
+}
+
 #[repr(u8)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Mask {
-	UpPosed(Regex) = 0,
-	DownPosed(Regex) = 1,
-	UpGrabbed(Regex) = 2,
-	DownGrabbed(Regex) = 3,
+	UpPosed(#[serde(with = "regex_serde")] Regex) = 0,
+	DownPosed(#[serde(with = "regex_serde")] Regex) = 1,
+	UpGrabbed(#[serde(with = "regex_serde")] Regex) = 2,
+	DownGrabbed(#[serde(with = "regex_serde")] Regex) = 3,
+}
+
+mod regex_serde {
+	use regex::Regex;
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(regex.as_str())
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+		let regex = String::deserialize(deserializer)?;
+		Regex::new(&regex).map_err(D::Error::custom)
+	}
 }
 
 impl Mask {
@@ -26,11 +344,84 @@ impl Mask {
 		// field, so we can read the discriminant without offsetting the pointer.
 		unsafe { *<*const _>::from(self).cast::<u8>() }
 	}
+
+	pub fn regex(&self) -> &Regex {
+		match self {
+			Mask::UpPosed(regex)
+			| Mask::DownPosed(regex)
+			| Mask::UpGrabbed(regex)
+			| Mask::DownGrabbed(regex) => regex,
+		}
+	}
+
+	/// Returns the same kind of mask matching `regex` instead.
+	pub fn with_regex(&self, regex: Regex) -> Self {
+		match self {
+			Mask::UpPosed(_) => Mask::UpPosed(regex),
+			Mask::DownPosed(_) => Mask::DownPosed(regex),
+			Mask::UpGrabbed(_) => Mask::UpGrabbed(regex),
+			Mask::DownGrabbed(_) => Mask::DownGrabbed(regex),
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Mask::UpPosed(_) => "posed up",
+			Mask::DownPosed(_) => "posed down",
+			Mask::UpGrabbed(_) => "grabbed up",
+			Mask::DownGrabbed(_) => "grabbed down",
+		}
+	}
+
+	/// Only grabbed masks increase the counter sent to the avatar, posed masks are only recorded.
+	pub fn is_grabbed(&self) -> bool {
+		matches!(self, Mask::UpGrabbed(_) | Mask::DownGrabbed(_))
+	}
+
+	pub fn side(&self) -> MaskSide {
+		match self {
+			Mask::UpPosed(_) | Mask::UpGrabbed(_) => MaskSide::Up,
+			Mask::DownPosed(_) | Mask::DownGrabbed(_) => MaskSide::Down,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
 	pub avatar_params: Vec<Mask>,
+	/// A baseline count for users who were already counting before using this app. The offset is
+	/// added on top of the counts stored in the database and doesn't create any rows itself. It no
+	/// longer applies once the counter was reset.
+	pub counter_offset: usize,
+	/// An optional bool parameter that is set to true on every count and back to false after
+	/// `pulse_duration`, e.g. to flash an animation on the avatar.
+	pub pulse_param: Option<String>,
+	pub pulse_duration: Duration,
+	/// When set, counts are collected for this long and written within a single transaction
+	/// instead of one write per count. Counting and OSC updates still happen right away.
+	pub batch_window: Option<Duration>,
+	/// The rolling window used for the longest streak record.
+	pub streak_window: Duration,
+	/// Whether muted masks stay muted across launches.
+	pub persist_muted_masks: bool,
+	pub trigger_type: TriggerType,
+	/// When set, the counter is sent through these segments instead of the counter and iteration
+	/// parameters.
+	pub segmented_output: Option<SegmentedOutput>,
+	/// Packets received during quiet hours are logged but not counted.
+	pub quiet_hours: Option<QuietHours>,
+	/// Resets the counter to zero on every launch instead of accumulating across launches.
+	pub reset_on_launch: bool,
+	/// When set, up and down masks triggering together only count once per interaction. By
+	/// default both count independently.
+	pub mutual_exclusion: Option<MutualExclusion>,
+	/// How long a mask doesn't count again after counting, by discriminant. Masks without a
+	/// cooldown count every time.
+	pub cooldowns: HashMap<u8, Duration>,
+	/// A bool parameter that resets the counter from within VRChat once it's held true for
+	/// `reset_hold`, so a brief accidental trigger doesn't reset anything. Disabled when unset.
+	pub reset_param: Option<String>,
+	pub reset_hold: Duration,
 }
 
 impl Config {
@@ -42,14 +433,90 @@ impl Config {
 			Mask::DownGrabbed(Regex::new("/avatar/parameters/.*?Mask_down_IsGrabbed")?),
 		];
 
-		Ok(Config { avatar_params })
+		Ok(Config {
+			avatar_params,
+			counter_offset: 0,
+			pulse_param: None,
+			pulse_duration: Duration::from_millis(250),
+			batch_window: None,
+			streak_window: Duration::from_secs(60),
+			persist_muted_masks: false,
+			trigger_type: TriggerType::Bool,
+			segmented_output: None,
+			quiet_hours: None,
+			reset_on_launch: false,
+			mutual_exclusion: None,
+			cooldowns: HashMap::new(),
+			reset_param: None,
+			reset_hold: Duration::from_secs(3),
+		})
+	}
+
+	/// The time left before the mask with `discriminant` can count again after it counted at
+	/// `last`, `None` when it can count right away.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use std::time::{Duration, Instant};
+	/// use vrcc_core::Config;
+	///
+	/// let mut config = Config::new().unwrap();
+	/// config.cooldowns.insert(2, Duration::from_secs(2));
+	///
+	/// let last = Instant::now();
+	/// let later = last + Duration::from_millis(500);
+	/// assert_eq!(
+	/// 	Some(Duration::from_millis(1500)),
+	/// 	config.cooldown_remaining(2, last, later)
+	/// );
+	/// assert_eq!(None, config.cooldown_remaining(2, last, last + Duration::from_secs(2)));
+	/// assert_eq!(None, config.cooldown_remaining(3, last, later));
+	/// ```
+	pub fn cooldown_remaining(
+		&self,
+		discriminant: u8,
+		last: Instant,
+		now: Instant,
+	) -> Option<Duration> {
+		let cooldown = self.cooldowns.get(&discriminant)?;
+		cooldown
+			.checked_sub(now.saturating_duration_since(last))
+			.filter(|remaining| !remaining.is_zero())
 	}
 }
 
+/// Returns the date of the latest counter reset, `None` when the counter was never reset.
+pub async fn last_reset(
+	db: &PrismaClient,
+) -> std::result::Result<Option<DateTime<FixedOffset>>, QueryError> {
+	let reset = db
+		.counter_reset()
+		.find_first(Vec::new())
+		.order_by(counter_reset::OrderByWithRelationParam::Date(
+			SortOrder::Desc,
+		))
+		.exec()
+		.await?;
+
+	Ok(reset.map(|reset| reset.date))
+}
+
+/// Filters counts to the ones made after the last reset. Resets only add a marker, so the counts
+/// before them are kept in the database.
+pub fn since_reset(last_reset: Option<DateTime<FixedOffset>>) -> Vec<mask_counter::WhereParam> {
+	last_reset
+		.map(|date| vec![mask_counter::date::gt(date)])
+		.unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub struct State {
-	pub config: Config,
+	/// Shared with the counter stream so changes from the settings apply immediately.
+	pub config: Arc<RwLock<Config>>,
 	pub db: Arc<PrismaClient>,
+	/// Why the saved config couldn't be loaded, the defaults are used instead.
+	pub config_error: Option<String>,
 }
 
 impl State {
@@ -61,7 +528,20 @@ impl State {
 			}
 		}
 
-		let config = Config::new().expect("error while getting config");
+		// NOTE: the config file can be edited by hand, a broken one shouldn't keep the app from
+		// starting
+		let (config, config_error) = match Config::load(preset::CONFIG_PATH) {
+			Ok(config) => (config, None),
+			Err(e) => {
+				error!(
+					"Couldn't load {}, using the default config: {}",
+					preset::CONFIG_PATH,
+					e
+				);
+				let config = Config::new().expect("error while getting config");
+				(config, Some(e.to_string()))
+			}
+		};
 
 		let db = Arc::new(
 			PrismaClient::_builder()
@@ -74,6 +554,10 @@ impl State {
 			.await
 			.expect("error while deploying db migration");
 
-		Self { config, db }
+		Self {
+			config: Arc::new(RwLock::new(config)),
+			db,
+			config_error,
+		}
 	}
 }
@@ -0,0 +1,252 @@
+use crate::{Config, Mask, MutualExclusion, QuietHours, SegmentedOutput, TriggerType};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Debug, path::Path, time::Duration};
+
+/// The part of the config that depends on the avatar rather than the user, so avatar creators can
+/// share a working setup. Personal settings like the counter offset or quiet hours are left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+	pub avatar_params: Vec<Mask>,
+	pub pulse_param: Option<String>,
+	pub pulse_duration: Duration,
+	pub trigger_type: TriggerType,
+	pub segmented_output: Option<SegmentedOutput>,
+	#[serde(default)]
+	pub mutual_exclusion: Option<MutualExclusion>,
+}
+
+impl Preset {
+	pub fn export(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+		let json = serde_json::to_string_pretty(self)?;
+		std::fs::write(path, json)?;
+		Ok(())
+	}
+
+	/// Reads a preset from a file. Invalid regexes fail the import as a whole.
+	pub fn import(path: impl AsRef<Path>) -> crate::Result<Self> {
+		let json = std::fs::read_to_string(path)?;
+		Ok(serde_json::from_str(&json)?)
+	}
+}
+
+/// The personal part of the config that presets leave out. Settings missing from the file keep
+/// their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+	pub counter_offset: usize,
+	pub streak_window: Duration,
+	pub persist_muted_masks: bool,
+	pub quiet_hours: Option<QuietHours>,
+	pub batch_window: Option<Duration>,
+	pub reset_on_launch: bool,
+	pub cooldowns: HashMap<u8, Duration>,
+	pub reset_param: Option<String>,
+	pub reset_hold: Duration,
+}
+
+impl Default for UserSettings {
+	fn default() -> Self {
+		// NOTE: the default regexes always compile
+		Config::new().unwrap().user_settings()
+	}
+}
+
+/// The settings changed from within the app are saved here, so they survive a restart. The file
+/// is a preset with the user settings in an extra `user` block, so it can still be imported as a
+/// preset.
+pub const CONFIG_PATH: &str = "vrc-counter-config.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ConfigFile {
+	#[serde(flatten)]
+	preset: Preset,
+	#[serde(default)]
+	user: UserSettings,
+}
+
+impl Config {
+	/// The default config with the settings saved at `path` applied on top. A missing file leaves
+	/// the defaults as they are.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use vrcc_core::Config;
+	///
+	/// let path = std::env::temp_dir().join(format!("vrcc-config-{}.json", std::process::id()));
+	/// let _ = std::fs::remove_file(&path);
+	/// assert!(Config::load(&path).unwrap().pulse_param.is_none());
+	///
+	/// let mut config = Config::new().unwrap();
+	/// config.pulse_param = Some(String::from("/avatar/parameters/mask_pulse"));
+	/// config.counter_offset = 42;
+	/// config.save(&path).unwrap();
+	///
+	/// let loaded = Config::load(&path).unwrap();
+	/// assert_eq!(config.pulse_param, loaded.pulse_param);
+	/// assert_eq!(42, loaded.counter_offset);
+	/// ```
+	pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+		let mut config = Config::new()?;
+		if path.as_ref().exists() {
+			let json = std::fs::read_to_string(path)?;
+			let file: ConfigFile = serde_json::from_str(&json)?;
+			config.apply_preset(file.preset);
+			config.apply_user_settings(file.user);
+		}
+		Ok(config)
+	}
+
+	/// Saves the preset and the user settings, see `load`.
+	pub fn save(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+		let file = ConfigFile {
+			preset: self.preset(),
+			user: self.user_settings(),
+		};
+		let json = serde_json::to_string_pretty(&file)?;
+		std::fs::write(path, json)?;
+		Ok(())
+	}
+
+	pub fn user_settings(&self) -> UserSettings {
+		UserSettings {
+			counter_offset: self.counter_offset,
+			streak_window: self.streak_window,
+			persist_muted_masks: self.persist_muted_masks,
+			quiet_hours: self.quiet_hours,
+			batch_window: self.batch_window,
+			reset_on_launch: self.reset_on_launch,
+			cooldowns: self.cooldowns.clone(),
+			reset_param: self.reset_param.clone(),
+			reset_hold: self.reset_hold,
+		}
+	}
+
+	pub fn apply_user_settings(&mut self, user: UserSettings) {
+		self.counter_offset = user.counter_offset;
+		self.streak_window = user.streak_window;
+		self.persist_muted_masks = user.persist_muted_masks;
+		self.quiet_hours = user.quiet_hours;
+		self.batch_window = user.batch_window;
+		self.reset_on_launch = user.reset_on_launch;
+		self.cooldowns = user.cooldowns;
+		self.reset_param = user.reset_param;
+		self.reset_hold = user.reset_hold;
+	}
+
+	pub fn preset(&self) -> Preset {
+		Preset {
+			avatar_params: self.avatar_params.clone(),
+			pulse_param: self.pulse_param.clone(),
+			pulse_duration: self.pulse_duration,
+			trigger_type: self.trigger_type,
+			segmented_output: self.segmented_output.clone(),
+			mutual_exclusion: self.mutual_exclusion,
+		}
+	}
+
+	/// Merges a preset into the config. Masks missing from the preset are kept as they are.
+	pub fn apply_preset(&mut self, preset: Preset) {
+		for mask in preset.avatar_params {
+			match self
+				.avatar_params
+				.iter_mut()
+				.find(|param| param.discriminant() == mask.discriminant())
+			{
+				Some(param) => *param = mask,
+				None => self.avatar_params.push(mask),
+			}
+		}
+		self.pulse_param = preset.pulse_param;
+		self.pulse_duration = preset.pulse_duration;
+		self.trigger_type = preset.trigger_type;
+		self.segmented_output = preset.segmented_output;
+		self.mutual_exclusion = preset.mutual_exclusion;
+	}
+
+	/// Describes every change applying `preset` would make, one line per changed setting.
+	///
+	/// # Example
+	///
+	/// ```rust
+	/// use regex::Regex;
+	/// use vrcc_core::{Config, Mask};
+	///
+	/// let mut config = Config::new().unwrap();
+	/// assert!(config.preset_changes(&config.preset()).is_empty());
+	///
+	/// let mut preset = config.preset();
+	/// preset.avatar_params = vec![Mask::UpGrabbed(Regex::new("^/avatar/parameters/Up$").unwrap())];
+	/// preset.pulse_param = Some(String::from("/avatar/parameters/mask_pulse"));
+	///
+	/// let changes = config.preset_changes(&preset);
+	/// assert_eq!(2, changes.len());
+	/// assert!(changes[0].starts_with("grabbed up:"));
+	///
+	/// config.apply_preset(preset.clone());
+	/// assert!(config.preset_changes(&preset).is_empty());
+	/// assert_eq!(4, config.avatar_params.len());
+	/// ```
+	pub fn preset_changes(&self, preset: &Preset) -> Vec<String> {
+		let mut changes = Vec::new();
+
+		for mask in &preset.avatar_params {
+			let current = self
+				.avatar_params
+				.iter()
+				.find(|param| param.discriminant() == mask.discriminant());
+			match current {
+				Some(current) if current.regex().as_str() == mask.regex().as_str() => {}
+				Some(current) => changes.push(format!(
+					"{}: {} -> {}",
+					mask.name(),
+					current.regex(),
+					mask.regex()
+				)),
+				None => changes.push(format!("{}: (none) -> {}", mask.name(), mask.regex())),
+			}
+		}
+
+		let current = self.preset();
+		push_change(
+			&mut changes,
+			"pulse parameter",
+			&current.pulse_param,
+			&preset.pulse_param,
+		);
+		push_change(
+			&mut changes,
+			"pulse duration",
+			&current.pulse_duration,
+			&preset.pulse_duration,
+		);
+		push_change(
+			&mut changes,
+			"trigger type",
+			&current.trigger_type,
+			&preset.trigger_type,
+		);
+		push_change(
+			&mut changes,
+			"segmented output",
+			&current.segmented_output,
+			&preset.segmented_output,
+		);
+		push_change(
+			&mut changes,
+			"mutual exclusion",
+			&current.mutual_exclusion,
+			&preset.mutual_exclusion,
+		);
+
+		changes
+	}
+}
+
+fn push_change<T: Debug>(changes: &mut Vec<String>, name: &str, current: &T, new: &T) {
+	let (current, new) = (format!("{:?}", current), format!("{:?}", new));
+	if current != new {
+		changes.push(format!("{}: {} -> {}", name, current, new));
+	}
+}
@@ -0,0 +1,252 @@
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use std::{
+	collections::VecDeque,
+	time::{Duration, Instant},
+};
+
+/// Counts further apart than this are taken as separate sessions when the best session is
+/// estimated from the stored counts, see `best_session`.
+pub const SESSION_GAP: Duration = Duration::from_secs(2 * 60 * 60);
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+	/// The most counts within the configured rolling window.
+	Streak = 0,
+	/// The highest total within a single launch of the app.
+	Session = 1,
+}
+
+impl RecordKind {
+	pub fn name(&self) -> &'static str {
+		match self {
+			RecordKind::Streak => "longest streak",
+			RecordKind::Session => "best session",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NewRecord {
+	pub kind: RecordKind,
+	pub value: usize,
+	/// `None` when there was no record of this kind yet.
+	pub previous: Option<usize>,
+	/// Only the count that first beats a record is announced, further counts of the same run
+	/// keep raising the record silently. The initial record is never announced.
+	pub announce: bool,
+}
+
+/// Keeps track of the counts within a rolling window and the total of the current session to
+/// detect when a record is beaten. Ties don't count as a new record.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use vrcc_core::records::{RecordKind, RecordTracker};
+///
+/// let start = Instant::now();
+/// let mut tracker = RecordTracker::new(Duration::from_secs(60), Some(2), None);
+///
+/// // The first count sets the initial session record but doesn't beat the streak of 2
+/// let records = tracker.count(start);
+/// assert_eq!(1, records.len());
+/// assert_eq!(RecordKind::Session, records[0].kind);
+/// assert_eq!(None, records[0].previous);
+/// assert!(!records[0].announce);
+///
+/// // Tying the streak of 2 isn't a new record
+/// let records = tracker.count(start + Duration::from_secs(1));
+/// assert!(records.iter().all(|record| record.kind != RecordKind::Streak));
+///
+/// let records = tracker.count(start + Duration::from_secs(2));
+/// let streak = records
+/// 	.iter()
+/// 	.find(|record| record.kind == RecordKind::Streak)
+/// 	.unwrap();
+/// assert_eq!(3, streak.value);
+/// assert!(streak.announce);
+///
+/// // Continuing the streak raises the record without announcing it again
+/// let records = tracker.count(start + Duration::from_secs(3));
+/// let streak = records
+/// 	.iter()
+/// 	.find(|record| record.kind == RecordKind::Streak)
+/// 	.unwrap();
+/// assert_eq!(4, streak.value);
+/// assert!(!streak.announce);
+///
+/// // Counts that left the window don't add to the streak anymore
+/// let records = tracker.count(start + Duration::from_secs(120));
+/// assert!(records.iter().all(|record| record.kind != RecordKind::Streak));
+/// assert_eq!(Some(4), tracker.best_streak());
+/// assert_eq!(Some(5), tracker.best_session());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordTracker {
+	window: Duration,
+	timestamps: VecDeque<Instant>,
+	session_total: usize,
+	best_streak: Option<usize>,
+	best_session: Option<usize>,
+	streak_run: bool,
+	session_run: bool,
+}
+
+impl RecordTracker {
+	pub fn new(window: Duration, best_streak: Option<usize>, best_session: Option<usize>) -> Self {
+		Self {
+			window,
+			timestamps: VecDeque::new(),
+			session_total: 0,
+			best_streak,
+			best_session,
+			streak_run: false,
+			session_run: false,
+		}
+	}
+
+	/// Continues a session that already has `total` counts, e.g. when the counter is restarted.
+	/// A session that holds the session record keeps its run so it isn't announced again.
+	pub fn with_session_total(mut self, total: usize) -> Self {
+		self.session_total = total;
+		self.session_run = total > 0 && self.best_session == Some(total);
+		self
+	}
+
+	pub fn session_total(&self) -> usize {
+		self.session_total
+	}
+
+	pub fn best_streak(&self) -> Option<usize> {
+		self.best_streak
+	}
+
+	pub fn best_session(&self) -> Option<usize> {
+		self.best_session
+	}
+
+	/// Registers a count that happened at `now` and returns every record that was set by it.
+	pub fn count(&mut self, now: Instant) -> Vec<NewRecord> {
+		self.timestamps.push_back(now);
+		while self
+			.timestamps
+			.front()
+			.is_some_and(|oldest| now.duration_since(*oldest) > self.window)
+		{
+			self.timestamps.pop_front();
+		}
+		self.session_total += 1;
+
+		let streak = beat(
+			RecordKind::Streak,
+			&mut self.best_streak,
+			&mut self.streak_run,
+			self.timestamps.len(),
+		);
+		let session = beat(
+			RecordKind::Session,
+			&mut self.best_session,
+			&mut self.session_run,
+			self.session_total,
+		);
+
+		streak.into_iter().chain(session).collect()
+	}
+}
+
+/// The most counts within `window` of each other, for seeding the streak record from the stored
+/// counts. `dates` have to be sorted from oldest to newest.
+///
+/// # Example
+///
+/// ```rust
+/// use chrono::{TimeDelta, Utc};
+/// use std::time::Duration;
+/// use vrcc_core::records::best_streak;
+///
+/// let start = Utc::now().fixed_offset();
+/// let dates = [0, 10, 20, 200, 210]
+/// 	.map(|seconds| start + TimeDelta::seconds(seconds));
+///
+/// assert_eq!(Some(3), best_streak(&dates, Duration::from_secs(60)));
+/// assert_eq!(None, best_streak(&[], Duration::from_secs(60)));
+/// ```
+pub fn best_streak(dates: &[DateTime<FixedOffset>], window: Duration) -> Option<usize> {
+	let window = TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX);
+	let mut oldest = 0;
+
+	dates
+		.iter()
+		.enumerate()
+		.map(|(index, date)| {
+			while *date - dates[oldest] > window {
+				oldest += 1;
+			}
+			index + 1 - oldest
+		})
+		.max()
+}
+
+/// The most counts of a single session, for seeding the session record from the stored counts.
+/// Launches of the app aren't stored, so counts less than `gap` apart are taken as one session.
+/// `dates` have to be sorted from oldest to newest.
+///
+/// # Example
+///
+/// ```rust
+/// use chrono::{TimeDelta, Utc};
+/// use vrcc_core::records::{best_session, SESSION_GAP};
+///
+/// let start = Utc::now().fixed_offset();
+/// let dates = [0, 1, 2, 10, 11]
+/// 	.map(|hours| start + TimeDelta::hours(hours));
+///
+/// assert_eq!(Some(3), best_session(&dates, SESSION_GAP));
+/// assert_eq!(None, best_session(&[], SESSION_GAP));
+/// ```
+pub fn best_session(dates: &[DateTime<FixedOffset>], gap: Duration) -> Option<usize> {
+	let gap = TimeDelta::from_std(gap).unwrap_or(TimeDelta::MAX);
+	let mut best = None;
+	let mut total = 0;
+
+	for (index, date) in dates.iter().enumerate() {
+		if index > 0 && *date - dates[index - 1] > gap {
+			total = 0;
+		}
+		total += 1;
+		best = best.max(Some(total));
+	}
+
+	best
+}
+
+fn beat(
+	kind: RecordKind,
+	best: &mut Option<usize>,
+	run: &mut bool,
+	value: usize,
+) -> Option<NewRecord> {
+	match *best {
+		Some(current) if value <= current => {
+			// NOTE: falling below the record ends the current run
+			if value < current {
+				*run = false;
+			}
+			None
+		}
+		previous => {
+			*best = Some(value);
+			let announce = previous.is_some() && !*run;
+			*run = true;
+
+			Some(NewRecord {
+				kind,
+				value,
+				previous,
+				announce,
+			})
+		}
+	}
+}
@@ -0,0 +1,59 @@
+use chrono::{Duration, Utc};
+use vrcc_core::{
+	chart::{export_png, ChartKind},
+	prisma::mask_counter,
+	Config,
+};
+
+const SIZE: (u32, u32) = (640, 480);
+
+/// Exports a chart and returns the bytes of the saved image.
+fn export(name: &str, kind: ChartKind, data: &[mask_counter::Data]) -> Vec<u8> {
+	let path = std::env::temp_dir().join(format!(
+		"vrcc-chart-{}-{:?}-{}.png",
+		name,
+		kind,
+		std::process::id()
+	));
+	let _ = std::fs::remove_file(&path);
+	let masks = Config::new().unwrap().avatar_params;
+
+	export_png(&path, SIZE, kind, &masks, data).unwrap();
+	std::fs::read(path).unwrap()
+}
+
+/// Reads the width and height from the header of a PNG.
+fn dimensions(png: &[u8]) -> (u32, u32) {
+	assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+	assert_eq!(b"IHDR", &png[12..16]);
+
+	(
+		u32::from_be_bytes(png[16..20].try_into().unwrap()),
+		u32::from_be_bytes(png[20..24].try_into().unwrap()),
+	)
+}
+
+#[test]
+fn empty_dataset_exports_an_empty_chart() {
+	for kind in ChartKind::ALL {
+		assert_eq!(SIZE, dimensions(&export("empty", kind, &[])));
+	}
+}
+
+#[test]
+fn counts_export_as_png() {
+	let start = Utc::now().fixed_offset();
+	let data = (0..10)
+		.map(|index| mask_counter::Data {
+			date: start + Duration::minutes(index),
+			r#type: (index % 4) as i32,
+		})
+		.collect::<Vec<_>>();
+
+	for kind in ChartKind::ALL {
+		let png = export("counts", kind, &data);
+		assert_eq!(SIZE, dimensions(&png));
+		// NOTE: the counts have to end up in the image, not just the empty chart
+		assert_ne!(export("counts-empty", kind, &[]), png);
+	}
+}
@@ -0,0 +1,471 @@
+use futures::{
+	channel::mpsc::{self, Receiver},
+	StreamExt,
+};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::{
+	collections::{HashMap, HashSet},
+	net::SocketAddr,
+	path::PathBuf,
+	sync::{atomic::AtomicUsize, Arc, Mutex, RwLock},
+	time::Duration,
+};
+use tokio::{net::UdpSocket, sync::Notify, time};
+use vrcc_core::{
+	counter::{
+		run_counter, Event, Shared, HEARTBEAT_INTERVAL, MASK_COUNTER_PARAM, MASK_ITERATION_PARAM,
+	},
+	last_sent::LastSent,
+	prisma::PrismaClient,
+	Config, MaskSide, MutualExclusion,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A counter running against its own database, with a socket standing in for VRChat on both ends.
+struct Harness {
+	dir: PathBuf,
+	db: Arc<PrismaClient>,
+	vrchat: UdpSocket,
+	counter_addr: SocketAddr,
+	events: Receiver<Event>,
+}
+
+impl Harness {
+	/// Starts a counter and skips the counter and iteration it sends on startup.
+	async fn start(name: &str, config: Config) -> Self {
+		let harness = Self::start_with(name, config, &[]).await;
+		harness.recv().await;
+		harness.recv().await;
+		harness
+	}
+
+	/// Starts a counter with `last_sent` as the values cached by a previous launch.
+	async fn start_with(name: &str, config: Config, last_sent: &[(&str, f32)]) -> Self {
+		let dir = temp_dir(name);
+		let values = last_sent.iter().copied().collect::<HashMap<_, _>>();
+		std::fs::write(
+			dir.join("last-sent.json"),
+			serde_json::json!({ "values": values }).to_string(),
+		)
+		.unwrap();
+		let db = Arc::new(
+			PrismaClient::_builder()
+				.with_url(format!("file:{}", dir.join("test.db").display()))
+				.build()
+				.await
+				.unwrap(),
+		);
+		db._migrate_deploy().await.unwrap();
+
+		let vrchat = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let counter_addr = socket.local_addr().unwrap();
+
+		let shared = Shared {
+			config: Arc::new(RwLock::new(config)),
+			osc_addresses: Arc::new(RwLock::new(Vec::new())),
+			muted_masks: Arc::new(RwLock::new(HashSet::new())),
+			pending_counts: Arc::new(Mutex::new(Vec::new())),
+			reset: Arc::new(Notify::new()),
+			session_total: Arc::new(AtomicUsize::new(0)),
+		};
+		let (tx, events) = mpsc::channel(100);
+		tokio::spawn(run_counter(
+			socket,
+			vrchat.local_addr().unwrap(),
+			Arc::clone(&db),
+			shared,
+			LastSent::load(dir.join("last-sent.json")),
+			tx,
+		));
+
+		Self {
+			dir,
+			db,
+			vrchat,
+			counter_addr,
+			events,
+		}
+	}
+
+	async fn send(&self, addr: &str, arg: OscType) {
+		let buf = rosc::encoder::encode(&OscPacket::Message(OscMessage {
+			addr: String::from(addr),
+			args: vec![arg],
+		}))
+		.unwrap();
+		self.send_raw(&buf).await;
+	}
+
+	async fn send_raw(&self, buf: &[u8]) {
+		self.vrchat.send_to(buf, self.counter_addr).await.unwrap();
+	}
+
+	/// Receives the next parameter the counter sent to VRChat.
+	async fn recv(&self) -> (String, OscType) {
+		let mut buf = [0u8; rosc::decoder::MTU];
+		let (size, _) = time::timeout(TIMEOUT, self.vrchat.recv_from(&mut buf))
+			.await
+			.expect("the counter didn't send anything")
+			.unwrap();
+		match rosc::decoder::decode_udp(&buf[..size]).unwrap() {
+			(_, OscPacket::Message(mut msg)) => (msg.addr, msg.args.remove(0)),
+			(_, OscPacket::Bundle(bundle)) => panic!("unexpected bundle: {:?}", bundle),
+		}
+	}
+
+	/// Waits for the next count and returns the discriminant of the mask that counted.
+	async fn counted(&mut self) -> u8 {
+		loop {
+			let event = time::timeout(TIMEOUT, self.events.next())
+				.await
+				.expect("the counter didn't count")
+				.unwrap();
+			if let Event::CounterUpdated(kind) = event {
+				return kind;
+			}
+		}
+	}
+
+	async fn stored_types(&self) -> Vec<i32> {
+		self.db
+			.mask_counter()
+			.find_many(Vec::new())
+			.exec()
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|counter| counter.r#type)
+			.collect()
+	}
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("vrcc-test-{}-{}", name, std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn float(addr: &str, value: f32) -> (String, OscType) {
+	(String::from(addr), OscType::Float(value))
+}
+
+#[tokio::test]
+async fn grabbing_counts_and_sends_the_counter() {
+	let mut harness = Harness::start("grab", Config::new().unwrap()).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(2, harness.counted().await);
+	assert_eq!(vec![2], harness.stored_types().await);
+}
+
+#[tokio::test]
+async fn posing_counts_without_sending_the_counter() {
+	let mut harness = Harness::start("pose", Config::new().unwrap()).await;
+
+	harness
+		.send("/avatar/parameters/Mask_down_IsPosed", OscType::Bool(true))
+		.await;
+	assert_eq!(1, harness.counted().await);
+	assert_eq!(vec![1], harness.stored_types().await);
+
+	// NOTE: the counter is still at zero when it's resent
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -1.0), harness.recv().await);
+}
+
+#[tokio::test]
+async fn releasing_does_not_count() {
+	let harness = Harness::start("release", Config::new().unwrap()).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(false))
+		.await;
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+
+	assert_eq!(float(MASK_COUNTER_PARAM, -1.0), harness.recv().await);
+	assert!(harness.stored_types().await.is_empty());
+}
+
+#[tokio::test]
+async fn avatar_change_resends_the_counter() {
+	let mut config = Config::new().unwrap();
+	config.counter_offset = 450;
+	let harness = Harness::start("avatar-change", config).await;
+
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.5), harness.recv().await);
+	assert_eq!(float(MASK_ITERATION_PARAM, -0.98), harness.recv().await);
+}
+
+#[tokio::test]
+async fn malformed_packets_are_skipped() {
+	let mut harness = Harness::start("malformed", Config::new().unwrap()).await;
+
+	harness.send_raw(&[0xff, 0x00, 0x13]).await;
+	harness
+		.send(
+			"/avatar/parameters/Mask_down_IsGrabbed",
+			OscType::Bool(true),
+		)
+		.await;
+
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(3, harness.counted().await);
+}
+
+#[tokio::test]
+async fn counter_rolls_over_into_the_next_iteration() {
+	let mut config = Config::new().unwrap();
+	config.counter_offset = 199;
+	let harness = Harness::start("rollover", config).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+
+	assert_eq!(float(MASK_COUNTER_PARAM, 1.0), harness.recv().await);
+	assert_eq!(float(MASK_ITERATION_PARAM, -0.99), harness.recv().await);
+}
+
+#[tokio::test]
+async fn mutual_exclusion_suppresses_the_other_side() {
+	let mut config = Config::new().unwrap();
+	config.mutual_exclusion = Some(MutualExclusion {
+		window: Duration::from_secs(60),
+		priority: None,
+	});
+	let mut harness = Harness::start("mutual-exclusion", config).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(2, harness.counted().await);
+
+	// NOTE: posing is a separate interaction and still counts
+	harness
+		.send(
+			"/avatar/parameters/Mask_down_IsGrabbed",
+			OscType::Bool(true),
+		)
+		.await;
+	harness
+		.send("/avatar/parameters/Mask_down_IsPosed", OscType::Bool(true))
+		.await;
+	assert_eq!(1, harness.counted().await);
+
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(vec![2, 1], harness.stored_types().await);
+}
+
+#[tokio::test]
+async fn priority_side_drops_the_held_back_count() {
+	let mut config = Config::new().unwrap();
+	config.mutual_exclusion = Some(MutualExclusion {
+		window: Duration::from_millis(500),
+		priority: Some(MaskSide::Down),
+	});
+	let mut harness = Harness::start("mutual-exclusion-priority", config).await;
+
+	// NOTE: the up side arrives first but waits for the window, so only the down side counts
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	harness
+		.send(
+			"/avatar/parameters/Mask_down_IsGrabbed",
+			OscType::Bool(true),
+		)
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(3, harness.counted().await);
+
+	// NOTE: without the priority side, the held back count counts once the window passed
+	harness
+		.send("/avatar/parameters/Mask_up_IsPosed", OscType::Bool(true))
+		.await;
+	assert_eq!(0, harness.counted().await);
+
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(vec![3, 0], harness.stored_types().await);
+}
+
+#[tokio::test]
+async fn rapid_batched_counts_are_all_stored() {
+	let mut config = Config::new().unwrap();
+	config.batch_window = Some(Duration::from_millis(100));
+	let mut harness = Harness::start("batch", config).await;
+
+	// NOTE: both counts likely land within the same millisecond
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	harness
+		.send(
+			"/avatar/parameters/Mask_down_IsGrabbed",
+			OscType::Bool(true),
+		)
+		.await;
+	assert_eq!(2, harness.counted().await);
+	assert_eq!(3, harness.counted().await);
+
+	time::sleep(Duration::from_millis(500)).await;
+	let mut stored = harness.stored_types().await;
+	stored.sort();
+	assert_eq!(vec![2, 3], stored);
+}
+
+#[tokio::test]
+async fn counter_sends_heartbeats() {
+	let mut harness = Harness::start("heartbeat", Config::new().unwrap()).await;
+
+	loop {
+		let event = time::timeout(HEARTBEAT_INTERVAL * 2, harness.events.next())
+			.await
+			.expect("the counter didn't send a heartbeat")
+			.unwrap();
+		if let Event::Heartbeat = event {
+			break;
+		}
+	}
+}
+
+#[tokio::test]
+async fn cooldown_ignores_repeated_counts() {
+	let mut config = Config::new().unwrap();
+	config.cooldowns.insert(2, Duration::from_secs(60));
+	let mut harness = Harness::start("cooldown", config).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	assert_eq!(2, harness.counted().await);
+
+	// NOTE: other masks aren't affected by the cooldown
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	harness
+		.send(
+			"/avatar/parameters/Mask_down_IsGrabbed",
+			OscType::Bool(true),
+		)
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.98), harness.recv().await);
+	assert_eq!(3, harness.counted().await);
+	assert_eq!(vec![2, 3], harness.stored_types().await);
+}
+
+#[tokio::test]
+async fn holding_the_reset_param_resets_the_counter() {
+	let mut config = Config::new().unwrap();
+	config.reset_param = Some(String::from("/avatar/parameters/mask_reset"));
+	config.reset_hold = Duration::from_millis(200);
+	let mut harness = Harness::start("remote-reset", config).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+
+	harness
+		.send("/avatar/parameters/mask_reset", OscType::Bool(true))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -1.0), harness.recv().await);
+	assert_eq!(float(MASK_ITERATION_PARAM, -1.0), harness.recv().await);
+
+	loop {
+		let event = time::timeout(TIMEOUT, harness.events.next())
+			.await
+			.expect("the counter wasn't reset")
+			.unwrap();
+		if let Event::Reset = event {
+			break;
+		}
+	}
+	let resets = harness
+		.db
+		.counter_reset()
+		.find_many(Vec::new())
+		.exec()
+		.await;
+	assert_eq!(1, resets.unwrap().len());
+}
+
+#[tokio::test]
+async fn releasing_the_reset_param_early_does_not_reset() {
+	let mut config = Config::new().unwrap();
+	config.reset_param = Some(String::from("/avatar/parameters/mask_reset"));
+	config.reset_hold = Duration::from_millis(200);
+	let harness = Harness::start("remote-reset-released", config).await;
+
+	harness
+		.send("/avatar/parameters/Mask_up_IsGrabbed", OscType::Bool(true))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+
+	harness
+		.send("/avatar/parameters/mask_reset", OscType::Bool(true))
+		.await;
+	harness
+		.send("/avatar/parameters/mask_reset", OscType::Bool(false))
+		.await;
+	time::sleep(Duration::from_millis(400)).await;
+
+	harness
+		.send("/avatar/change", OscType::String(String::from("avtr_test")))
+		.await;
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.99), harness.recv().await);
+	let resets = harness
+		.db
+		.counter_reset()
+		.find_many(Vec::new())
+		.exec()
+		.await;
+	assert!(resets.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn last_sent_values_of_an_old_output_are_dropped() {
+	let harness = Harness::start_with(
+		"last-sent",
+		Config::new().unwrap(),
+		&[
+			(MASK_COUNTER_PARAM, -0.5),
+			("/avatar/parameters/mask_counter_0", 0.3),
+		],
+	)
+	.await;
+
+	// NOTE: only the restored counter is sent before the values read from the database
+	assert_eq!(float(MASK_COUNTER_PARAM, -0.5), harness.recv().await);
+	assert_eq!(float(MASK_COUNTER_PARAM, -1.0), harness.recv().await);
+	assert_eq!(float(MASK_ITERATION_PARAM, -1.0), harness.recv().await);
+
+	time::sleep(Duration::from_millis(200)).await;
+	let cached = LastSent::load(harness.dir.join("last-sent.json"));
+	let mut cached = cached.values().map(|(addr, _)| addr).collect::<Vec<_>>();
+	cached.sort();
+	assert_eq!(vec![MASK_COUNTER_PARAM, MASK_ITERATION_PARAM], cached);
+}
@@ -0,0 +1,6 @@
+-- CreateTable
+CREATE TABLE "StatRecord" (
+    "kind" INTEGER NOT NULL PRIMARY KEY,
+    "value" INTEGER NOT NULL,
+    "date" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- CreateTable
+CREATE TABLE "MutedMask" (
+    "type" INTEGER NOT NULL PRIMARY KEY
+);
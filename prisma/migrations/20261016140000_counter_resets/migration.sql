@@ -0,0 +1,4 @@
+-- CreateTable
+CREATE TABLE "CounterReset" (
+    "date" DATETIME NOT NULL PRIMARY KEY DEFAULT CURRENT_TIMESTAMP
+);
@@ -6,23 +6,46 @@ mod logger;
 
 use futures::{channel::mpsc::Sender, SinkExt, Stream};
 use iced::{
-	widget::{button, container, scrollable, text, Column},
-	Element, Length, Subscription, Task, Theme,
+	widget::{button, checkbox, container, row, scrollable, text, Column},
+	window, Element, Length, Subscription, Task, Theme,
 };
 use logger::Logger;
 use modal::modal;
-use rosc::{OscMessage, OscPacket, OscType};
-use rust_decimal::{prelude::ToPrimitive, Decimal};
-use rust_decimal_macros::dec;
-use std::{sync::Arc, time::Duration};
-use tokio::net::UdpSocket;
-use tracing::{debug, error, info};
+use std::{
+	collections::{HashMap, HashSet},
+	io::ErrorKind,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex, RwLock,
+	},
+	time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, sync::Notify, time};
+use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use tracing_unwrap::ResultExt;
-use vrcc_core::Mask;
+use vrcc_core::{
+	counter::{
+		best_records, flush_counts, run_counter, Event, PendingCount, Shared, HEARTBEAT_INTERVAL,
+	},
+	last_reset,
+	last_sent::{LastSent, LAST_SENT_PATH},
+	prisma::muted_mask,
+	records::RecordKind,
+	since_reset, Config,
+};
 
-const MASK_COUNTER_PARAM: &str = "/avatar/parameters/mask_counter";
-const MASK_ITERATION_PARAM: &str = "/avatar/parameters/mask_iteration";
+/// VRChat's OSC input port.
+const VRCHAT_OSC_ADDR: &str = "127.0.0.1:9000";
+/// VRChat's OSC output port, the counter listens on it.
+const COUNTER_OSC_ADDR: &str = "127.0.0.1:9001";
+/// How long to wait before binding again when the counter's port is in use.
+const BIND_RETRY: Duration = Duration::from_secs(1);
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+const SEND_ERROR_DURATION: Duration = Duration::from_secs(10);
+/// The counter is restarted when it hasn't sent a heartbeat for this long.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(3 * HEARTBEAT_INTERVAL.as_secs());
+/// How often the remaining cooldowns are redrawn while any mask is cooling down.
+const COOLDOWN_REFRESH: Duration = Duration::from_millis(100);
 
 // TODO: auto-run on steamvr
 // TODO: add plotters-iced: https://github.com/joylei/plotters-iced
@@ -34,52 +57,55 @@ fn main() -> iced::Result {
 	iced::application("VRC Counter", Counter::update, Counter::view)
 		.theme(Counter::theme)
 		.subscription(Counter::subscription)
+		// NOTE: batched counts are flushed before the window closes
+		.exit_on_close_request(false)
 		.run_with(Counter::new)
 }
 
-/// A blend tree is used inside the Unity Editor and uses a float parameter with a minimum range of
-/// negative one (-1) to a maximum range of positive one (+1). VRChat clamps remote parameters
-/// across the network to two decimal places (0.99). This gives a possible accurate range of 200
-/// values and this function is used to convert the integer form into the float that represents
-/// that integer by returning a `Decimal`.
-///
-/// Note that the function is not aware of a minimum/maximum range, therefore a `Decimal` can be
-/// returned with a value over positive one by giving a number greater than 200.
-///
-/// # Example
-///
-/// ```rust
-/// let num = 200;
-/// let dec = int_to_decimal(num).to_f32().unwrap();
-///
-/// assert_eq!(1.0, dec)
-/// ```
-fn int_to_decimal(num: usize) -> Decimal {
-	let output = Decimal::new(num as i64, 0) * dec!(0.01);
-	dec!(-1.0) + output
-}
-
 #[derive(Debug, Clone)]
 enum ScreenKind {
 	TestModal,
+	Stats,
+	Settings,
 }
 
 #[derive(Debug)]
 enum Screen {
 	TestModal(test_modal::TestModal),
-}
-
-#[derive(Debug, Clone)]
-enum Event {
-	CounterUpdated,
-	Log(String),
+	Stats(stats::Stats),
+	Settings(settings::Settings),
 }
 
 #[derive(Debug)]
 struct Counter {
 	state: vrcc_core::State,
+	/// Distinct OSC addresses received by the counter stream, newest last.
+	osc_addresses: Arc<RwLock<Vec<String>>>,
 	mask_counter: usize,
+	mask_counts: HashMap<u8, usize>,
+	/// When every mask last counted, to show the remaining cooldowns.
+	last_counted: HashMap<u8, Instant>,
+	/// Discriminants of the masks that currently don't count, shared with the counter stream.
+	muted_masks: Arc<RwLock<HashSet<u8>>>,
+	/// Counts the counter stream hasn't written yet, shared to flush them when closing.
+	pending_counts: Arc<Mutex<Vec<PendingCount>>>,
+	/// Notifies the counter stream to reset the counter.
+	reset: Arc<Notify>,
+	/// Whether the reset button was pressed and waits for confirmation.
+	confirm_reset: bool,
+	/// The counts since launch, shared with the counter so a restart continues the session.
+	session_total: Arc<AtomicUsize>,
+	quiet_hours_active: bool,
+	best_streak: Option<usize>,
+	best_session: Option<usize>,
 	modal: Option<Screen>,
+	toast: Option<String>,
+	toast_id: usize,
+	send_error: Option<String>,
+	send_error_id: usize,
+	/// Bumped to restart the counter stream, see `WATCHDOG_TIMEOUT`.
+	counter_generation: usize,
+	last_heartbeat: Instant,
 	logs: Vec<String>,
 }
 
@@ -89,6 +115,17 @@ enum Message {
 	ModalChanged(ScreenKind),
 	ModalClosed,
 	TestModal(test_modal::Message),
+	Stats(stats::Message),
+	Settings(settings::Message),
+	MaskMuted(u8, bool),
+	ToastExpired(usize),
+	SendErrorExpired(usize),
+	ResetPressed,
+	ResetConfirmed,
+	ResetCancelled,
+	WatchdogTick,
+	CooldownTick,
+	CloseRequested(window::Id),
 }
 
 impl Counter {
@@ -96,25 +133,123 @@ impl Counter {
 		let state = futures::executor::block_on(vrcc_core::State::new());
 
 		let db = &state.db;
-		let data =
-			futures::executor::block_on(db.mask_counter().find_many(Vec::new()).exec()).unwrap();
-
-		(
-			Counter {
-				state,
-				mask_counter: data.len(),
-				modal: None,
-				logs: Vec::new(),
-			},
-			Task::none(),
+		let config = state.config.read().unwrap().clone();
+
+		if config.reset_on_launch {
+			info!("Resetting the counter on launch");
+			futures::executor::block_on(db.counter_reset().create(Vec::new()).exec()).unwrap();
+		}
+
+		let last_reset = futures::executor::block_on(last_reset(db)).unwrap();
+		let data = futures::executor::block_on(
+			db.mask_counter().find_many(since_reset(last_reset)).exec(),
 		)
+		.unwrap();
+
+		let counter_offset = if last_reset.is_some() {
+			0
+		} else {
+			config.counter_offset
+		};
+		let mask_counter = counter_offset + data.len();
+		let mut mask_counts: HashMap<u8, usize> = HashMap::new();
+		for counter in &data {
+			*mask_counts.entry(counter.r#type as u8).or_default() += 1;
+		}
+
+		let muted_masks = if config.persist_muted_masks {
+			futures::executor::block_on(db.muted_mask().find_many(Vec::new()).exec())
+				.unwrap()
+				.into_iter()
+				.map(|muted_mask| muted_mask.r#type as u8)
+				.collect()
+		} else {
+			HashSet::new()
+		};
+
+		let (best_streak, best_session) =
+			futures::executor::block_on(best_records(db, config.streak_window)).unwrap();
+
+		let mut counter = Counter {
+			state,
+			osc_addresses: Arc::new(RwLock::new(Vec::new())),
+			mask_counter,
+			mask_counts,
+			last_counted: HashMap::new(),
+			muted_masks: Arc::new(RwLock::new(muted_masks)),
+			pending_counts: Arc::new(Mutex::new(Vec::new())),
+			reset: Arc::new(Notify::new()),
+			confirm_reset: false,
+			session_total: Arc::new(AtomicUsize::new(0)),
+			quiet_hours_active: false,
+			best_streak,
+			best_session,
+			modal: None,
+			toast: None,
+			toast_id: 0,
+			send_error: None,
+			send_error_id: 0,
+			counter_generation: 0,
+			last_heartbeat: Instant::now(),
+			logs: Vec::new(),
+		};
+
+		let task = if config.reset_on_launch {
+			counter.show_toast(String::from("The counter was reset on launch"))
+		} else {
+			Task::none()
+		};
+
+		(counter, task)
 	}
 
 	fn update(&mut self, message: Message) -> Task<Message> {
 		match message {
 			Message::Event(event) => match event {
-				Event::CounterUpdated => {
+				Event::CounterUpdated(kind) => {
 					self.mask_counter += 1;
+					*self.mask_counts.entry(kind).or_default() += 1;
+					self.last_counted.insert(kind, Instant::now());
+					Task::none()
+				}
+				Event::RecordSet(record) => {
+					match record.kind {
+						RecordKind::Streak => self.best_streak = Some(record.value),
+						RecordKind::Session => self.best_session = Some(record.value),
+					}
+
+					if record.announce {
+						self.show_toast(format!("New {}: {}!", record.kind.name(), record.value))
+					} else {
+						Task::none()
+					}
+				}
+				// NOTE: the settings read the addresses when rendering, this only causes a redraw
+				Event::OscReceived(_) => Task::none(),
+				Event::QuietHoursChanged(active) => {
+					self.quiet_hours_active = active;
+					Task::none()
+				}
+				Event::SendFailed(error) => {
+					self.send_error_id += 1;
+					self.send_error = Some(error);
+
+					let id = self.send_error_id;
+					Task::perform(time::sleep(SEND_ERROR_DURATION), move |_| {
+						Message::SendErrorExpired(id)
+					})
+				}
+				Event::SendRecovered => {
+					self.send_error = None;
+					Task::none()
+				}
+				Event::Reset => {
+					self.mask_counter = 0;
+					self.mask_counts.clear();
+					self.show_toast(String::from("The counter was reset"))
+				}
+				Event::Heartbeat => {
+					self.last_heartbeat = Instant::now();
 					Task::none()
 				}
 				Event::Log(value) => {
@@ -122,11 +257,60 @@ impl Counter {
 					Task::none()
 				}
 			},
+			Message::ResetPressed => {
+				self.confirm_reset = true;
+				Task::none()
+			}
+			Message::ResetConfirmed => {
+				self.confirm_reset = false;
+				self.reset.notify_one();
+				Task::none()
+			}
+			Message::ResetCancelled => {
+				self.confirm_reset = false;
+				Task::none()
+			}
+			// NOTE: only redraws the remaining cooldowns
+			Message::CooldownTick => Task::none(),
+			Message::WatchdogTick => {
+				if self.last_heartbeat.elapsed() < WATCHDOG_TIMEOUT {
+					return Task::none();
+				}
+
+				warn!(
+					"The counter hasn't responded for {} seconds, restarting it",
+					self.last_heartbeat.elapsed().as_secs()
+				);
+				self.counter_generation += 1;
+				self.last_heartbeat = Instant::now();
+				self.show_toast(String::from(
+					"The counter stopped responding and was restarted",
+				))
+			}
 			Message::ModalChanged(kind) => match kind {
 				ScreenKind::TestModal => {
 					self.modal = Some(Screen::TestModal(test_modal::TestModal::new()));
 					Task::none()
 				}
+				ScreenKind::Stats => {
+					let config = self.state.config.read().unwrap();
+					self.modal = Some(Screen::Stats(stats::Stats::new(
+						Arc::clone(&self.state.db),
+						config.avatar_params.clone(),
+						self.best_streak,
+						self.best_session,
+						self.session_total.load(Ordering::Relaxed),
+						config.streak_window,
+					)));
+					Task::none()
+				}
+				ScreenKind::Settings => {
+					self.modal = Some(Screen::Settings(settings::Settings::new(
+						Arc::clone(&self.state.config),
+						Arc::clone(&self.osc_addresses),
+					)));
+					Task::none()
+				}
 			},
 			Message::ModalClosed => {
 				self.modal = None;
@@ -144,15 +328,219 @@ impl Counter {
 					_ => Task::none(),
 				}
 			}
+			Message::Stats(message) => {
+				let Some(screen) = &mut self.modal else {
+					return Task::none();
+				};
+				match screen {
+					Screen::Stats(stats) => stats.update(message).map(Message::Stats),
+					_ => Task::none(),
+				}
+			}
+			Message::Settings(message) => {
+				let Some(screen) = &mut self.modal else {
+					return Task::none();
+				};
+				match screen {
+					Screen::Settings(settings) => {
+						let persisted = self.state.config.read().unwrap().persist_muted_masks;
+						settings.update(message);
+						if persisted != self.state.config.read().unwrap().persist_muted_masks {
+							return self.sync_muted_masks();
+						}
+						Task::none()
+					}
+					_ => Task::none(),
+				}
+			}
+			Message::MaskMuted(kind, muted) => {
+				{
+					let mut muted_masks = self.muted_masks.write().unwrap();
+					if muted {
+						muted_masks.insert(kind);
+					} else {
+						muted_masks.remove(&kind);
+					}
+				}
+
+				if !self.state.config.read().unwrap().persist_muted_masks {
+					return Task::none();
+				}
+
+				let db = Arc::clone(&self.state.db);
+				Task::future(async move {
+					let result = if muted {
+						db.muted_mask()
+							.upsert(
+								muted_mask::r#type::equals(kind as i32),
+								muted_mask::create(kind as i32, Vec::new()),
+								Vec::new(),
+							)
+							.exec()
+							.await
+							.map(|_| ())
+					} else {
+						db.muted_mask()
+							.delete_many(vec![muted_mask::r#type::equals(kind as i32)])
+							.exec()
+							.await
+							.map(|_| ())
+					};
+
+					if let Err(e) = result {
+						error!("{}", e);
+					}
+				})
+				.discard()
+			}
+			Message::ToastExpired(id) => {
+				// NOTE: a newer toast replaced this one and has its own timer
+				if id == self.toast_id {
+					self.toast = None;
+				}
+				Task::none()
+			}
+			Message::SendErrorExpired(id) => {
+				if id == self.send_error_id {
+					self.send_error = None;
+				}
+				Task::none()
+			}
+			Message::CloseRequested(id) => {
+				let db = Arc::clone(&self.state.db);
+				let pending_counts = Arc::clone(&self.pending_counts);
+				Task::perform(
+					async move {
+						if flush_counts(&db, &pending_counts).await {
+							error!("Some batched counts couldn't be written before closing");
+						}
+					},
+					move |_| id,
+				)
+				.then(window::close)
+			}
 		}
 	}
 
+	/// Stores the current mutes once they're persisted, or forgets the stored ones so they aren't
+	/// restored if persisting is turned on again later.
+	fn sync_muted_masks(&self) -> Task<Message> {
+		let db = Arc::clone(&self.state.db);
+		let persist = self.state.config.read().unwrap().persist_muted_masks;
+		let muted_masks = self
+			.muted_masks
+			.read()
+			.unwrap()
+			.iter()
+			.map(|kind| *kind as i32)
+			.collect::<Vec<_>>();
+
+		Task::future(async move {
+			let result = if persist {
+				let upserts = muted_masks
+					.into_iter()
+					.map(|kind| {
+						db.muted_mask().upsert(
+							muted_mask::r#type::equals(kind),
+							muted_mask::create(kind, Vec::new()),
+							Vec::new(),
+						)
+					})
+					.collect::<Vec<_>>();
+				db._batch(upserts).await.map(|_| ())
+			} else {
+				db.muted_mask()
+					.delete_many(Vec::new())
+					.exec()
+					.await
+					.map(|_| ())
+			};
+
+			if let Err(e) = result {
+				error!("{}", e);
+			}
+		})
+		.discard()
+	}
+
+	fn show_toast(&mut self, toast: String) -> Task<Message> {
+		self.toast_id += 1;
+		self.toast = Some(toast);
+
+		let id = self.toast_id;
+		Task::perform(time::sleep(TOAST_DURATION), move |_| {
+			Message::ToastExpired(id)
+		})
+	}
+
 	fn view(&self) -> Element<Message> {
 		let counter_text = text(self.mask_counter);
 		let modal_button =
 			button(text("Test Modal")).on_press(Message::ModalChanged(ScreenKind::TestModal));
-
-		let content = container(Column::new().push(counter_text).push(modal_button));
+		let stats_button = button(text("Stats")).on_press(Message::ModalChanged(ScreenKind::Stats));
+		let settings_button =
+			button(text("Settings")).on_press(Message::ModalChanged(ScreenKind::Settings));
+		let reset_row = if self.confirm_reset {
+			row![
+				text("Reset the counter to zero?"),
+				button(text("Reset")).on_press(Message::ResetConfirmed),
+				button(text("Cancel")).on_press(Message::ResetCancelled),
+			]
+			.spacing(10)
+		} else {
+			row![button(text("Reset counter")).on_press(Message::ResetPressed)]
+		};
+
+		let muted_masks = self.muted_masks.read().unwrap();
+		let config = self.state.config.read().unwrap();
+		let mask_rows = Column::from_vec(
+			config
+				.avatar_params
+				.iter()
+				.map(|param| {
+					let kind = param.discriminant();
+					row![
+						text(param.name()).width(120),
+						text(self.mask_counts.get(&kind).copied().unwrap_or_default()).width(60),
+						checkbox("Muted", muted_masks.contains(&kind))
+							.on_toggle(move |muted| Message::MaskMuted(kind, muted)),
+						text(
+							self.cooldown_remaining(&config, kind)
+								.map(|remaining| {
+									format!("cooldown {:.1}s", remaining.as_secs_f32())
+								})
+								.unwrap_or_default()
+						)
+						.size(12),
+					]
+					.spacing(10)
+					.into()
+				})
+				.collect(),
+		);
+		drop(config);
+
+		let content = container(
+			Column::new()
+				.push_maybe(self.state.config_error.as_ref().map(|e| {
+					text(format!(
+						"Couldn't load the saved settings, the defaults are used until they're saved again: {}",
+						e
+					))
+				}))
+				.push_maybe(self.send_error.as_ref().map(text))
+				.push_maybe(self.toast.as_ref().map(text))
+				.push_maybe(
+					self.quiet_hours_active
+						.then(|| text("Paused during quiet hours, packets aren't counted")),
+				)
+				.push(counter_text)
+				.push(mask_rows)
+				.push(modal_button)
+				.push(stats_button)
+				.push(settings_button)
+				.push(reset_row),
+		);
 
 		let logs = container(scrollable(Column::from_vec(
 			self.logs.iter().map(|log| text(log).into()).collect(),
@@ -166,10 +554,12 @@ impl Counter {
 			.height(Length::Fill);
 
 		if let Some(screen) = &self.modal {
-			let Screen::TestModal(test) = screen;
-			modal(root_container, test.view().map(Message::TestModal), || {
-				Message::ModalClosed
-			})
+			let screen = match screen {
+				Screen::TestModal(test) => test.view().map(Message::TestModal),
+				Screen::Stats(stats) => stats.view().map(Message::Stats),
+				Screen::Settings(settings) => settings.view().map(Message::Settings),
+			};
+			modal(root_container, screen, || Message::ModalClosed)
 		} else {
 			root_container.into()
 		}
@@ -179,274 +569,92 @@ impl Counter {
 		let sub_logger = Subscription::run(log_stream).map(Message::Event);
 
 		struct Listen;
-		let sub_counter =
-			Subscription::run_with_id(std::any::TypeId::of::<Listen>(), self.counter_stream())
-				.map(Message::Event);
+		// NOTE: a new id makes iced drop the old stream and run a fresh one
+		let sub_counter = Subscription::run_with_id(
+			(std::any::TypeId::of::<Listen>(), self.counter_generation),
+			self.counter_stream(),
+		)
+		.map(Message::Event);
+
+		let sub_watchdog = iced::time::every(HEARTBEAT_INTERVAL).map(|_| Message::WatchdogTick);
+
+		let sub_cooldown = if self.cooling_down() {
+			iced::time::every(COOLDOWN_REFRESH).map(|_| Message::CooldownTick)
+		} else {
+			Subscription::none()
+		};
+
+		let sub_close = window::close_requests().map(Message::CloseRequested);
+
+		Subscription::batch([
+			sub_logger,
+			sub_counter,
+			sub_watchdog,
+			sub_cooldown,
+			sub_close,
+		])
+	}
+
+	fn cooldown_remaining(&self, config: &Config, kind: u8) -> Option<Duration> {
+		let last = self.last_counted.get(&kind)?;
+		config.cooldown_remaining(kind, *last, Instant::now())
+	}
 
-		Subscription::batch([sub_logger, sub_counter])
+	fn cooling_down(&self) -> bool {
+		let config = self.state.config.read().unwrap();
+		self.last_counted
+			.keys()
+			.any(|kind| self.cooldown_remaining(&config, *kind).is_some())
 	}
 
 	fn counter_stream(&self) -> impl Stream<Item = Event> {
 		let db = Arc::clone(&self.state.db);
-		let avatar_params = self.state.config.avatar_params.clone();
+		let shared = Shared {
+			config: Arc::clone(&self.state.config),
+			osc_addresses: Arc::clone(&self.osc_addresses),
+			muted_masks: Arc::clone(&self.muted_masks),
+			pending_counts: Arc::clone(&self.pending_counts),
+			reset: Arc::clone(&self.reset),
+			session_total: Arc::clone(&self.session_total),
+		};
 
-		// TODO: refactor redundant code
 		// TODO: handle all unwraps to print to stdout ideally in a func that returns result
 		iced::stream::channel(0, |mut tx: Sender<Event>| async move {
-			// TODO: handle AddrInUse error
-			let socket = UdpSocket::bind("127.0.0.1:9001").await.unwrap();
-
-			// NOTE: get the start of the current day
-			// let start_cur_date = Local::now()
-			// 	.fixed_offset()
-			// 	.with_hour(0)
-			// 	.unwrap()
-			// 	.with_minute(0)
-			// 	.unwrap()
-			// 	.with_second(0)
-			// 	.unwrap()
-			// 	.with_nanosecond(0)
-			// 	.unwrap();
-
-			let mut data_len = db
-				.mask_counter()
-				.find_many(vec![
-					// NOTE: only select records within the current day and grabbed instead of posed
-					// mask_counter::date::gt(start_cur_date),
-					// mask_counter::WhereParam::Or(vec![
-					// 	mask_counter::r#type::equals(
-					// 		Mask::UpGrabbed(Regex::new("").unwrap()).discriminant() as i32,
-					// 	),
-					// 	mask_counter::r#type::equals(
-					// 		Mask::DownGrabbed(Regex::new("").unwrap()).discriminant() as i32,
-					// 	),
-					// ]),
-				])
-				.exec()
-				.await
-				.unwrap()
-				.len();
-			let mut iteration_amount = 0;
-
-			let mut buf = [0u8; rosc::decoder::MTU];
-			loop {
-				if data_len >= 200 {
-					info!("Setting iteration_amount and data_len!");
-					info!("iteration_amount: {}", iteration_amount);
-					info!("data_len: {}", data_len);
-					iteration_amount += data_len / 200;
-					data_len %= 200;
-					info!("iteration_amount: {}", iteration_amount);
-					info!("data_len: {}", data_len);
-					let output = int_to_decimal(iteration_amount);
-					let iteration_buf = rosc::encoder::encode(&OscPacket::Message(OscMessage {
-						addr: String::from(MASK_ITERATION_PARAM),
-						args: vec![OscType::Float(output.to_f32().unwrap())],
-					}))
-					.unwrap();
-					socket
-						.send_to(&iteration_buf, "127.0.0.1:9000")
-						.await
-						.unwrap_or_log();
-				}
-				match socket.recv_from(&mut buf).await {
-					Ok((size, addr)) => {
-						debug!("Received packet with size {} from: {}", &size, &addr);
-						let (_, packet) = rosc::decoder::decode_udp(&buf[..size]).unwrap();
-						match packet {
-							OscPacket::Message(msg) => {
-								debug!("OSC address: {}", &msg.addr);
-								debug!("OSC arguments: {:?}", &msg.args);
-								if let Some(arg) = msg.args.first()
-									&& let OscType::Bool(value) = arg
-									&& *value
-								{
-									let addr = msg.addr.as_str();
-									for param in &avatar_params {
-										match param {
-											Mask::UpPosed(regex) => {
-												if regex.find(addr).is_some() {
-													info!("posed up!");
-
-													if let Err(e) = db
-														.mask_counter()
-														.create(
-															param.discriminant() as i32,
-															Vec::new(),
-														)
-														.exec()
-														.await
-													{
-														error!("{}", e);
-													} else {
-														tx.send(Event::CounterUpdated)
-															.await
-															.unwrap();
-													}
-												}
-											}
-											Mask::DownPosed(regex) => {
-												if regex.find(addr).is_some() {
-													info!("posed down!");
-													if let Err(e) = db
-														.mask_counter()
-														.create(
-															param.discriminant() as i32,
-															Vec::new(),
-														)
-														.exec()
-														.await
-													{
-														error!("{}", e);
-													} else {
-														tx.send(Event::CounterUpdated)
-															.await
-															.unwrap();
-													}
-												}
-											}
-											Mask::UpGrabbed(regex) => {
-												if regex.find(addr).is_some() {
-													info!("grabbed up!");
-													if let Err(e) = db
-														.mask_counter()
-														.create(
-															param.discriminant() as i32,
-															Vec::new(),
-														)
-														.exec()
-														.await
-													{
-														error!("{}", e);
-													} else {
-														data_len += 1;
-
-														let output = int_to_decimal(data_len);
-														info!("output: {}", output);
-														info!("from address: {}", &msg.addr);
-														info!(
-															"affected address: {}",
-															MASK_COUNTER_PARAM
-														);
-
-														let counter_buf = rosc::encoder::encode(
-															&OscPacket::Message(OscMessage {
-																addr: String::from(
-																	MASK_COUNTER_PARAM,
-																),
-																args: vec![OscType::Float(
-																	output.to_f32().unwrap(),
-																)],
-															}),
-														)
-														.unwrap();
-														if let Err(e) = socket
-															.send_to(&counter_buf, "127.0.0.1:9000")
-															.await
-														{
-															error!("{}", e);
-														}
-
-														tx.send(Event::CounterUpdated)
-															.await
-															.unwrap();
-													}
-												}
-											}
-											Mask::DownGrabbed(regex) => {
-												if regex.find(addr).is_some() {
-													info!("grabbed down!");
-													if let Err(e) = db
-														.mask_counter()
-														.create(
-															param.discriminant() as i32,
-															Vec::new(),
-														)
-														.exec()
-														.await
-													{
-														error!("{}", e);
-													} else {
-														data_len += 1;
-
-														let output = int_to_decimal(data_len);
-														info!("output: {}", output);
-														info!("from address: {}", &msg.addr);
-														info!(
-															"affected address: {}",
-															MASK_COUNTER_PARAM
-														);
-
-														let counter_buf = rosc::encoder::encode(
-															&OscPacket::Message(OscMessage {
-																addr: String::from(
-																	MASK_COUNTER_PARAM,
-																),
-																args: vec![OscType::Float(
-																	output.to_f32().unwrap(),
-																)],
-															}),
-														)
-														.unwrap();
-														if let Err(e) = socket
-															.send_to(&counter_buf, "127.0.0.1:9000")
-															.await
-														{
-															error!("{}", e);
-														}
-
-														tx.send(Event::CounterUpdated)
-															.await
-															.unwrap();
-													}
-												}
-											}
-										}
-									}
-								} else if msg.addr == "/avatar/change" {
-									// TODO: configure avatar ids
-
-									let output = int_to_decimal(data_len);
-									info!("output: {}", output);
-									info!("from address: {}", &msg.addr);
-									info!("affected address: {}", MASK_COUNTER_PARAM);
-
-									let counter_buf =
-										rosc::encoder::encode(&OscPacket::Message(OscMessage {
-											addr: String::from(MASK_COUNTER_PARAM),
-											args: vec![OscType::Float(output.to_f32().unwrap())],
-										}))
-										.unwrap();
-									if let Err(e) =
-										socket.send_to(&counter_buf, "127.0.0.1:9000").await
-									{
-										error!("{}", e);
-									}
-									info!("iteration_amount: {}", iteration_amount);
-									let output = int_to_decimal(iteration_amount);
-									let iteration_buf =
-										rosc::encoder::encode(&OscPacket::Message(OscMessage {
-											addr: String::from(MASK_ITERATION_PARAM),
-											args: vec![OscType::Float(output.to_f32().unwrap())],
-										}))
-										.unwrap();
-									if let Err(e) =
-										socket.send_to(&iteration_buf, "127.0.0.1:9000").await
-									{
-										error!("{}", e);
-									}
-								}
-							}
-							OscPacket::Bundle(bundle) => {
-								debug!("OSC Bundle: {:?}", &bundle);
-							}
+			// NOTE: the port stays in use until a previous counter is dropped, e.g. after a
+			// restart by the watchdog, or while another app listens on it
+			let mut warned = false;
+			let socket = loop {
+				match UdpSocket::bind(COUNTER_OSC_ADDR).await {
+					Ok(socket) => break socket,
+					Err(e) if e.kind() == ErrorKind::AddrInUse => {
+						if !warned {
+							warn!(
+								"{} is in use by another app, retrying every {:?}",
+								COUNTER_OSC_ADDR, BIND_RETRY
+							);
+							warned = true;
 						}
+						// NOTE: waiting for the port isn't a stopped counter, the watchdog
+						// shouldn't restart it
+						tx.send(Event::Heartbeat).await.unwrap();
+						time::sleep(BIND_RETRY).await;
 					}
-					Err(e) => {
-						error!("Error receiving from socket: {}", e);
-					}
+					Err(e) => panic!("Couldn't listen on {}: {}", COUNTER_OSC_ADDR, e),
 				}
+			};
+			if warned {
+				info!("Listening on {}", COUNTER_OSC_ADDR);
 			}
+
+			run_counter(
+				socket,
+				VRCHAT_OSC_ADDR.parse().unwrap(),
+				db,
+				shared,
+				LastSent::load(LAST_SENT_PATH),
+				tx,
+			)
+			.await;
 		})
 	}
 
@@ -455,6 +663,21 @@ impl Counter {
 	}
 }
 
+/// The background and rounded border shared by every modal.
+fn modal_style(_theme: &Theme) -> container::Style {
+	let palette = iced::theme::palette::Palette::CATPPUCCIN_FRAPPE;
+
+	container::Style {
+		text_color: Some(palette.text),
+		background: Some(iced::Background::Color(palette.background)),
+		border: iced::Border {
+			radius: 8.0.into(),
+			..Default::default()
+		},
+		..Default::default()
+	}
+}
+
 fn log_stream() -> impl Stream<Item = Event> {
 	iced::stream::channel(0, |tx: Sender<Event>| async move {
 		tracing_subscriber::registry()
@@ -488,26 +711,691 @@ mod test_modal {
 		pub fn update(&mut self, _message: Message) {}
 
 		pub fn view(&self) -> Element<Message> {
-			let text_color = iced::theme::palette::Palette::CATPPUCCIN_FRAPPE.text;
-			let bg_color = iced::theme::palette::Palette::CATPPUCCIN_FRAPPE.background;
-
 			container(Column::new().push(text("Hello modal!")).spacing(20))
 				.width(300)
 				.padding(10)
-				.style(move |_theme| container::Style {
-					text_color: Some(text_color),
-					background: Some(iced::Background::Color(bg_color)),
-					border: iced::Border {
-						radius: 8.0.into(),
-						..Default::default()
-					},
-					..Default::default()
-				})
+				.style(super::modal_style)
 				.into()
 		}
 	}
 }
 
+mod stats {
+	use iced::{
+		widget::{button, container, pick_list, row, text, text_input, Column},
+		Element, Task,
+	};
+	use std::{sync::Arc, time::Duration};
+	use tracing::{error, info};
+	use vrcc_core::{
+		chart::{self, ChartKind},
+		prisma::{mask_counter, PrismaClient, SortOrder},
+		Mask,
+	};
+
+	const DEFAULT_CHART_PATH: &str = "vrc-counter-chart.png";
+
+	#[derive(Debug)]
+	pub struct Stats {
+		db: Arc<PrismaClient>,
+		masks: Vec<Mask>,
+		best_streak: Option<usize>,
+		best_session: Option<usize>,
+		session: usize,
+		streak_window: Duration,
+		chart_kind: ChartKind,
+		chart_width: String,
+		chart_height: String,
+		chart_path: String,
+		chart_status: Option<String>,
+	}
+
+	#[derive(Debug, Clone)]
+	pub enum Message {
+		ChartKindSelected(ChartKind),
+		ChartWidthChanged(String),
+		ChartHeightChanged(String),
+		ChartPathChanged(String),
+		SaveChart,
+		ChartSaved(Result<String, String>),
+	}
+
+	impl Stats {
+		pub fn new(
+			db: Arc<PrismaClient>,
+			masks: Vec<Mask>,
+			best_streak: Option<usize>,
+			best_session: Option<usize>,
+			session: usize,
+			streak_window: Duration,
+		) -> Self {
+			Self {
+				db,
+				masks,
+				best_streak,
+				best_session,
+				session,
+				streak_window,
+				chart_kind: ChartKind::TimeSeries,
+				chart_width: String::from("1280"),
+				chart_height: String::from("720"),
+				chart_path: String::from(DEFAULT_CHART_PATH),
+				chart_status: None,
+			}
+		}
+
+		pub fn update(&mut self, message: Message) -> Task<Message> {
+			match message {
+				Message::ChartKindSelected(kind) => self.chart_kind = kind,
+				Message::ChartWidthChanged(width) => self.chart_width = width,
+				Message::ChartHeightChanged(height) => self.chart_height = height,
+				Message::ChartPathChanged(path) => self.chart_path = path,
+				Message::SaveChart => {
+					let size = match (self.chart_width.parse(), self.chart_height.parse()) {
+						(Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+						_ => {
+							self.chart_status = Some(String::from(
+								"The width and height must be positive numbers",
+							));
+							return Task::none();
+						}
+					};
+
+					self.chart_status = Some(String::from("Saving the chart..."));
+					return Task::perform(
+						save_chart(
+							Arc::clone(&self.db),
+							self.masks.clone(),
+							self.chart_kind,
+							size,
+							self.chart_path.clone(),
+						),
+						Message::ChartSaved,
+					);
+				}
+				Message::ChartSaved(result) => {
+					self.chart_status = Some(match result {
+						Ok(path) => {
+							info!("Saved the chart to {}", path);
+							format!("Saved the chart to {}", path)
+						}
+						Err(e) => {
+							error!("{}", e);
+							format!("Couldn't save the chart: {}", e)
+						}
+					});
+				}
+			}
+
+			Task::none()
+		}
+
+		pub fn view(&self) -> Element<Message> {
+			let best_streak = match self.best_streak {
+				Some(streak) => format!(
+					"Longest streak: {} counts within {} seconds",
+					streak,
+					self.streak_window.as_secs()
+				),
+				None => String::from("Longest streak: no record yet"),
+			};
+			let best_session = match self.best_session {
+				Some(session) => format!("Best session: {} counts", session),
+				None => String::from("Best session: no record yet"),
+			};
+
+			let chart_kind = pick_list(
+				ChartKind::ALL,
+				Some(self.chart_kind),
+				Message::ChartKindSelected,
+			);
+			let chart_width =
+				text_input("Width", &self.chart_width).on_input(Message::ChartWidthChanged);
+			let chart_height =
+				text_input("Height", &self.chart_height).on_input(Message::ChartHeightChanged);
+			let chart_path =
+				text_input("Image file", &self.chart_path).on_input(Message::ChartPathChanged);
+			let save_button = button(text("Save chart as image")).on_press(Message::SaveChart);
+
+			container(
+				Column::new()
+					.push(text(best_streak))
+					.push(text(best_session))
+					.push(text(format!("Current session: {} counts", self.session)))
+					.push(chart_kind)
+					.push(row![chart_width, text("x"), chart_height].spacing(10))
+					.push(chart_path)
+					.push(save_button)
+					.push_maybe(self.chart_status.as_ref().map(text))
+					.spacing(20),
+			)
+			.width(400)
+			.padding(10)
+			.style(super::modal_style)
+			.into()
+		}
+	}
+
+	async fn save_chart(
+		db: Arc<PrismaClient>,
+		masks: Vec<Mask>,
+		kind: ChartKind,
+		size: (u32, u32),
+		path: String,
+	) -> Result<String, String> {
+		let data = db
+			.mask_counter()
+			.find_many(Vec::new())
+			.order_by(mask_counter::OrderByWithRelationParam::Date(SortOrder::Asc))
+			.exec()
+			.await
+			.map_err(|e| e.to_string())?;
+
+		chart::export_png(&path, size, kind, &masks, &data).map_err(|e| e.to_string())?;
+		Ok(path)
+	}
+}
+
+mod settings {
+	use chrono::NaiveTime;
+	use iced::{
+		widget::{
+			button, checkbox, container, pick_list, row, scrollable, text, text_input, Column,
+		},
+		Element,
+	};
+	use regex::Regex;
+	use std::{
+		collections::HashMap,
+		fmt,
+		sync::{Arc, RwLock},
+		time::Duration,
+	};
+	use tracing::{error, info};
+	use vrcc_core::{
+		preset::{Preset, UserSettings, CONFIG_PATH},
+		Config, QuietHours,
+	};
+
+	const DEFAULT_PRESET_PATH: &str = "vrc-counter-preset.json";
+	/// The width of the labels in front of the user settings, so their inputs line up.
+	const LABEL_WIDTH: u16 = 200;
+	/// The format quiet hours are typed in, in local time.
+	const TIME_FORMAT: &str = "%H:%M";
+	const SETTINGS_SAVED: &str =
+		"Saved the settings, the counter offset and streak window apply from the next launch";
+
+	#[derive(Debug, Clone, PartialEq)]
+	pub struct MaskOption {
+		discriminant: u8,
+		name: &'static str,
+	}
+
+	impl fmt::Display for MaskOption {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			write!(f, "{}", self.name)
+		}
+	}
+
+	#[derive(Debug)]
+	pub struct Settings {
+		config: Arc<RwLock<Config>>,
+		osc_addresses: Arc<RwLock<Vec<String>>>,
+		selected_address: Option<String>,
+		selected_mask: Option<MaskOption>,
+		preset_path: String,
+		preset_status: Option<String>,
+		/// An imported preset and the changes it makes, waiting to be confirmed.
+		pending_preset: Option<(Preset, Vec<String>)>,
+		// NOTE: the user settings are kept as typed and only parsed when they're saved
+		counter_offset: String,
+		/// In seconds.
+		streak_window: String,
+		persist_muted_masks: bool,
+		/// Both empty when there are no quiet hours.
+		quiet_start: String,
+		quiet_end: String,
+		/// In milliseconds, empty when counts aren't batched.
+		batch_window: String,
+		reset_on_launch: bool,
+		/// In milliseconds by discriminant, empty for masks without a cooldown.
+		cooldowns: HashMap<u8, String>,
+		/// Empty when the counter can't be reset remotely.
+		reset_param: String,
+		/// In milliseconds.
+		reset_hold: String,
+		settings_status: Option<String>,
+	}
+
+	#[derive(Debug, Clone)]
+	pub enum Message {
+		AddressSelected(String),
+		MaskSelected(MaskOption),
+		ApplyAddress,
+		UseAsResetParam,
+		ClearAddresses,
+		PresetPathChanged(String),
+		ExportPreset,
+		ImportPreset,
+		ApplyPreset,
+		DiscardPreset,
+		CounterOffsetChanged(String),
+		StreakWindowChanged(String),
+		PersistMutedMasksToggled(bool),
+		QuietStartChanged(String),
+		QuietEndChanged(String),
+		BatchWindowChanged(String),
+		ResetOnLaunchToggled(bool),
+		CooldownChanged(u8, String),
+		ResetParamChanged(String),
+		ResetHoldChanged(String),
+		SaveSettings,
+	}
+
+	impl Settings {
+		pub fn new(config: Arc<RwLock<Config>>, osc_addresses: Arc<RwLock<Vec<String>>>) -> Self {
+			let user = config.read().unwrap().user_settings();
+			let cooldowns = user
+				.cooldowns
+				.iter()
+				.map(|(kind, cooldown)| (*kind, cooldown.as_millis().to_string()))
+				.collect();
+			Self {
+				config,
+				osc_addresses,
+				selected_address: None,
+				selected_mask: None,
+				preset_path: String::from(DEFAULT_PRESET_PATH),
+				preset_status: None,
+				pending_preset: None,
+				counter_offset: user.counter_offset.to_string(),
+				streak_window: user.streak_window.as_secs().to_string(),
+				persist_muted_masks: user.persist_muted_masks,
+				quiet_start: format_time(user.quiet_hours.map(|quiet_hours| quiet_hours.start)),
+				quiet_end: format_time(user.quiet_hours.map(|quiet_hours| quiet_hours.end)),
+				batch_window: user
+					.batch_window
+					.map(|window| window.as_millis().to_string())
+					.unwrap_or_default(),
+				reset_on_launch: user.reset_on_launch,
+				cooldowns,
+				reset_param: user.reset_param.unwrap_or_default(),
+				reset_hold: user.reset_hold.as_millis().to_string(),
+				settings_status: None,
+			}
+		}
+
+		pub fn update(&mut self, message: Message) {
+			match message {
+				Message::AddressSelected(address) => self.selected_address = Some(address),
+				Message::MaskSelected(mask) => self.selected_mask = Some(mask),
+				Message::ApplyAddress => {
+					let (Some(address), Some(mask)) = (&self.selected_address, &self.selected_mask)
+					else {
+						return;
+					};
+
+					// NOTE: an escaped and anchored address can't fail to compile
+					let regex = Regex::new(&format!("^{}$", regex::escape(address))).unwrap();
+					let mut config = self.config.write().unwrap();
+					if let Some(param) = config
+						.avatar_params
+						.iter_mut()
+						.find(|param| param.discriminant() == mask.discriminant)
+					{
+						info!("{} now matches {}", param.name(), address);
+						*param = param.with_regex(regex);
+					}
+					self.preset_status = save_config(&config);
+				}
+				Message::UseAsResetParam => {
+					let Some(address) = &self.selected_address else {
+						return;
+					};
+
+					info!("{} now resets the counter", address);
+					let mut config = self.config.write().unwrap();
+					config.reset_param = Some(address.clone());
+					self.reset_param = address.clone();
+					self.preset_status = save_config(&config);
+				}
+				Message::ClearAddresses => {
+					self.osc_addresses.write().unwrap().clear();
+					self.selected_address = None;
+				}
+				Message::PresetPathChanged(path) => self.preset_path = path,
+				Message::ExportPreset => {
+					let preset = self.config.read().unwrap().preset();
+					self.preset_status = Some(match preset.export(&self.preset_path) {
+						Ok(()) => format!("Exported the preset to {}", self.preset_path),
+						Err(e) => {
+							error!("{}", e);
+							format!("Couldn't export the preset: {}", e)
+						}
+					});
+				}
+				Message::ImportPreset => match Preset::import(&self.preset_path) {
+					Ok(preset) => {
+						let changes = self.config.read().unwrap().preset_changes(&preset);
+						self.preset_status = None;
+						self.pending_preset = Some((preset, changes));
+					}
+					Err(e) => {
+						error!("{}", e);
+						self.preset_status = Some(format!("Couldn't import the preset: {}", e));
+					}
+				},
+				Message::ApplyPreset => {
+					if let Some((preset, _)) = self.pending_preset.take() {
+						info!("Applying the preset from {}", self.preset_path);
+						let mut config = self.config.write().unwrap();
+						config.apply_preset(preset);
+						self.preset_status = Some(
+							save_config(&config)
+								.unwrap_or_else(|| String::from("Applied the preset")),
+						);
+					}
+				}
+				Message::DiscardPreset => self.pending_preset = None,
+				Message::CounterOffsetChanged(offset) => self.counter_offset = offset,
+				Message::StreakWindowChanged(window) => self.streak_window = window,
+				Message::PersistMutedMasksToggled(persist) => self.persist_muted_masks = persist,
+				Message::QuietStartChanged(start) => self.quiet_start = start,
+				Message::QuietEndChanged(end) => self.quiet_end = end,
+				Message::BatchWindowChanged(window) => self.batch_window = window,
+				Message::ResetOnLaunchToggled(reset) => self.reset_on_launch = reset,
+				Message::CooldownChanged(kind, cooldown) => {
+					self.cooldowns.insert(kind, cooldown);
+				}
+				Message::ResetParamChanged(param) => self.reset_param = param,
+				Message::ResetHoldChanged(hold) => self.reset_hold = hold,
+				Message::SaveSettings => {
+					let mut config = self.config.write().unwrap();
+					match self.user_settings(config.user_settings()) {
+						Ok(user) => {
+							info!("Saving the user settings");
+							config.apply_user_settings(user);
+							self.settings_status = Some(
+								save_config(&config)
+									.unwrap_or_else(|| String::from(SETTINGS_SAVED)),
+							);
+						}
+						Err(e) => self.settings_status = Some(e),
+					}
+				}
+			}
+		}
+
+		/// Parses the typed user settings on top of `user`, returns a message for the user when
+		/// any of them is invalid.
+		fn user_settings(&self, mut user: UserSettings) -> Result<UserSettings, String> {
+			user.counter_offset = self.counter_offset.trim().parse().map_err(|_| {
+				String::from("The counter offset must be a whole number of zero or more")
+			})?;
+			user.streak_window = match self.streak_window.trim().parse::<u64>() {
+				Ok(secs) if secs > 0 => Duration::from_secs(secs),
+				_ => {
+					return Err(String::from(
+						"The streak window must be a whole number of seconds above zero",
+					))
+				}
+			};
+			user.persist_muted_masks = self.persist_muted_masks;
+			let (quiet_start, quiet_end) = (self.quiet_start.trim(), self.quiet_end.trim());
+			user.quiet_hours = if quiet_start.is_empty() && quiet_end.is_empty() {
+				None
+			} else {
+				let (Some(start), Some(end)) = (parse_time(quiet_start), parse_time(quiet_end))
+				else {
+					return Err(String::from(
+						"Quiet hours need both a start and an end time like 23:00",
+					));
+				};
+				Some(QuietHours { start, end })
+			};
+			user.batch_window = match self.batch_window.trim() {
+				"" => None,
+				window => {
+					let millis = window
+						.parse::<u64>()
+						.ok()
+						.filter(|millis| *millis > 0)
+						.ok_or_else(|| {
+							String::from("The batch window must be a whole number of milliseconds")
+						})?;
+					Some(Duration::from_millis(millis))
+				}
+			};
+			user.reset_on_launch = self.reset_on_launch;
+			for (kind, cooldown) in &self.cooldowns {
+				match cooldown.trim() {
+					"" => {
+						user.cooldowns.remove(kind);
+					}
+					cooldown => {
+						let millis = cooldown
+							.parse::<u64>()
+							.ok()
+							.filter(|millis| *millis > 0)
+							.ok_or_else(|| {
+								String::from("Cooldowns must be whole numbers of milliseconds")
+							})?;
+						user.cooldowns.insert(*kind, Duration::from_millis(millis));
+					}
+				}
+			}
+			user.reset_param = match self.reset_param.trim() {
+				"" => None,
+				param => Some(String::from(param)),
+			};
+			user.reset_hold = self
+				.reset_hold
+				.trim()
+				.parse::<u64>()
+				.ok()
+				.filter(|millis| *millis > 0)
+				.map(Duration::from_millis)
+				.ok_or_else(|| {
+					String::from("The reset hold must be a whole number of milliseconds above zero")
+				})?;
+
+			Ok(user)
+		}
+
+		pub fn view(&self) -> Element<Message> {
+			if let Some((_, changes)) = &self.pending_preset {
+				return self.preset_view(changes);
+			}
+
+			let config = self.config.read().unwrap();
+			let mask_options: Vec<MaskOption> = config
+				.avatar_params
+				.iter()
+				.map(|param| MaskOption {
+					discriminant: param.discriminant(),
+					name: param.name(),
+				})
+				.collect();
+			let current_regex = self.selected_mask.as_ref().and_then(|mask| {
+				config
+					.avatar_params
+					.iter()
+					.find(|param| param.discriminant() == mask.discriminant)
+					.map(|param| format!("Currently matches: {}", param.regex()))
+			});
+
+			let addresses = self.osc_addresses.read().unwrap().clone();
+			let address_list = pick_list(
+				addresses,
+				self.selected_address.clone(),
+				Message::AddressSelected,
+			)
+			.placeholder("Recently received addresses");
+			let mask_list = pick_list(
+				mask_options,
+				self.selected_mask.clone(),
+				Message::MaskSelected,
+			)
+			.placeholder("Mask");
+
+			let apply_button = button(text("Use address")).on_press_maybe(
+				(self.selected_address.is_some() && self.selected_mask.is_some())
+					.then_some(Message::ApplyAddress),
+			);
+			let reset_button = button(text("Use as reset parameter")).on_press_maybe(
+				self.selected_address
+					.is_some()
+					.then_some(Message::UseAsResetParam),
+			);
+			let clear_button = button(text("Clear addresses")).on_press(Message::ClearAddresses);
+
+			let preset_path =
+				text_input("Preset file", &self.preset_path).on_input(Message::PresetPathChanged);
+			let export_button = button(text("Export preset")).on_press(Message::ExportPreset);
+			let import_button = button(text("Import preset")).on_press(Message::ImportPreset);
+
+			let counter_offset = row![
+				text("Counter offset").width(LABEL_WIDTH),
+				text_input("0", &self.counter_offset).on_input(Message::CounterOffsetChanged),
+			]
+			.spacing(10);
+			let streak_window = row![
+				text("Streak window (s)").width(LABEL_WIDTH),
+				text_input("60", &self.streak_window).on_input(Message::StreakWindowChanged),
+			]
+			.spacing(10);
+			let persist_muted_masks = checkbox(
+				"Keep muted masks muted after a restart",
+				self.persist_muted_masks,
+			)
+			.on_toggle(Message::PersistMutedMasksToggled);
+			let quiet_hours = row![
+				text("Quiet hours").width(LABEL_WIDTH),
+				text_input("23:00", &self.quiet_start).on_input(Message::QuietStartChanged),
+				text("to"),
+				text_input("07:00", &self.quiet_end).on_input(Message::QuietEndChanged),
+			]
+			.spacing(10);
+			let batch_window = row![
+				text("Batch window (ms)").width(LABEL_WIDTH),
+				text_input("Off", &self.batch_window).on_input(Message::BatchWindowChanged),
+			]
+			.spacing(10);
+			let cooldowns = Column::from_vec(
+				config
+					.avatar_params
+					.iter()
+					.map(|param| {
+						let kind = param.discriminant();
+						let cooldown = self.cooldowns.get(&kind).map(String::as_str);
+						row![
+							text(format!("{} cooldown (ms)", param.name())).width(LABEL_WIDTH),
+							text_input("None", cooldown.unwrap_or_default())
+								.on_input(move |cooldown| Message::CooldownChanged(kind, cooldown)),
+						]
+						.spacing(10)
+						.into()
+					})
+					.collect(),
+			)
+			.spacing(10);
+			let reset_param = row![
+				text("Reset parameter").width(LABEL_WIDTH),
+				text_input("Off", &self.reset_param).on_input(Message::ResetParamChanged),
+			]
+			.spacing(10);
+			let reset_hold = row![
+				text("Reset hold (ms)").width(LABEL_WIDTH),
+				text_input("3000", &self.reset_hold).on_input(Message::ResetHoldChanged),
+			]
+			.spacing(10);
+			let reset_on_launch =
+				checkbox("Reset the counter on every launch", self.reset_on_launch)
+					.on_toggle(Message::ResetOnLaunchToggled);
+			let save_button = button(text("Save settings")).on_press(Message::SaveSettings);
+
+			container(scrollable(
+				Column::new()
+					.push(address_list)
+					.push(mask_list)
+					.push_maybe(current_regex.map(text))
+					.push(row![apply_button, reset_button, clear_button].spacing(10))
+					.push(preset_path)
+					.push(row![export_button, import_button].spacing(10))
+					.push_maybe(self.preset_status.as_ref().map(text))
+					.push(counter_offset)
+					.push(streak_window)
+					.push(persist_muted_masks)
+					.push(quiet_hours)
+					.push(batch_window)
+					.push(reset_on_launch)
+					.push(cooldowns)
+					.push(reset_param)
+					.push(reset_hold)
+					.push(save_button)
+					.push_maybe(self.settings_status.as_ref().map(text))
+					.spacing(20),
+			))
+			.width(500)
+			.padding(10)
+			.style(super::modal_style)
+			.into()
+		}
+
+		fn preset_view(&self, changes: &[String]) -> Element<Message> {
+			let summary = if changes.is_empty() {
+				Column::new().push(text("The preset matches the current config"))
+			} else {
+				Column::from_vec(
+					changes
+						.iter()
+						.map(|change| text(format!("~ {}", change)).into())
+						.collect(),
+				)
+			};
+			let apply_button = button(text("Apply")).on_press(Message::ApplyPreset);
+			let discard_button = button(text("Cancel")).on_press(Message::DiscardPreset);
+
+			container(
+				Column::new()
+					.push(text(format!("Changes from {}", self.preset_path)))
+					.push(summary.spacing(5))
+					.push(row![apply_button, discard_button].spacing(10))
+					.spacing(20),
+			)
+			.width(500)
+			.padding(10)
+			.style(super::modal_style)
+			.into()
+		}
+	}
+
+	/// Formats a time of the quiet hours as typed in the settings.
+	fn format_time(time: Option<NaiveTime>) -> String {
+		time.map(|time| time.format(TIME_FORMAT).to_string())
+			.unwrap_or_default()
+	}
+
+	fn parse_time(time: &str) -> Option<NaiveTime> {
+		NaiveTime::parse_from_str(time, TIME_FORMAT).ok()
+	}
+
+	/// Saves the config so changes from the settings survive a restart. Returns a message for the
+	/// user when saving failed.
+	fn save_config(config: &Config) -> Option<String> {
+		match config.save(CONFIG_PATH) {
+			Ok(()) => None,
+			Err(e) => {
+				error!("{}", e);
+				Some(format!(
+					"Couldn't save the config, the change only lasts until the app is closed: {}",
+					e
+				))
+			}
+		}
+	}
+}
+
 // TODO: add animations with lilt
 mod modal {
 	//! License SPDX: GPL-3.0-only